@@ -0,0 +1,352 @@
+//! Price alerts checked against the stored closes after an update.
+//!
+//! A rule file has one rule per line, `#` starts a comment:
+//!
+//! ```text
+//! DE0008469008 above 13500    # close crosses above a level
+//! DE0008469008 below 12000    # close crosses below a level
+//! DE0007164600 move 5         # close moves by at least 5% against the previous one
+//! DE0007164600 high 52        # close above the closes of the previous 52 days
+//! DE0007164600 low 20         # close below the closes of the previous 20 days
+//! DE0007164600 cross 20 50    # 20 day moving average crosses the 50 day one
+//! ```
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::error_def::*;
+use crate::indicators;
+use crate::ohlc::OHLC;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Above(f64),
+    Below(f64),
+    /// Absolute change in percent.
+    Move(f64),
+    High(usize),
+    Low(usize),
+    /// Fast and slow simple moving average.
+    Cross(usize, usize),
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Above(level) => write!(f, "above {}", level),
+            Condition::Below(level) => write!(f, "below {}", level),
+            Condition::Move(percent) => write!(f, "move {}", percent),
+            Condition::High(n) => write!(f, "high {}", n),
+            Condition::Low(n) => write!(f, "low {}", n),
+            Condition::Cross(fast, slow) => write!(f, "cross {} {}", fast, slow),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub isin: String,
+    pub condition: Condition,
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    /// `ISIN CONDITION ARGS...` as in the rule file.
+    fn from_str(s: &str) -> Result<Rule> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let condition = match fields.as_slice() {
+            [_, "above", level] => Condition::Above(level.parse()?),
+            [_, "below", level] => Condition::Below(level.parse()?),
+            [_, "move", percent] => Condition::Move(percent.parse()?),
+            [_, "high", n] => Condition::High(n.parse()?),
+            [_, "low", n] => Condition::Low(n.parse()?),
+            [_, "cross", fast, slow] => Condition::Cross(fast.parse()?, slow.parse()?),
+            _ => bail!("Invalid alert rule: {}", s),
+        };
+        match condition {
+            Condition::High(0) | Condition::Low(0) | Condition::Cross(0, _) => {
+                bail!("Invalid alert rule: {}", s)
+            }
+            Condition::Cross(fast, slow) if fast >= slow => {
+                bail!("Invalid alert rule: {}", s)
+            }
+            _ => (),
+        }
+        Ok(Rule {
+            isin: fields[0].to_string(),
+            condition,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.isin, self.condition)
+    }
+}
+
+pub fn read_rules<R: Read>(r: R) -> Result<Vec<Rule>> {
+    let mut rules = vec![];
+    for (i, line) in BufReader::new(r).lines().enumerate() {
+        let line = line?;
+        let rule = line.split('#').next().unwrap().trim();
+        if rule.is_empty() {
+            continue;
+        }
+        match rule.parse() {
+            Ok(rule) => rules.push(rule),
+            Err(e) => bail!("Line {}: {}", i + 1, e),
+        }
+    }
+    Ok(rules)
+}
+
+/// A rule that fired on one day.
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub rule: Rule,
+    pub day: NaiveDate,
+    pub close: f64,
+    pub message: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} close {}: {}",
+            self.day, self.rule.isin, self.close, self.message
+        )
+    }
+}
+
+/// The alerts of a rule on the days of `history` after `since`, or on the last
+/// day only without `since`. `history` is the full history, oldest first.
+pub fn evaluate(
+    rule: &Rule,
+    history: &[(NaiveDate, OHLC)],
+    since: Option<NaiveDate>,
+) -> Vec<Alert> {
+    let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+    let first = match since {
+        Some(since) => history
+            .iter()
+            .position(|e| e.0 > since)
+            .unwrap_or(closes.len()),
+        None => closes.len().saturating_sub(1),
+    };
+    let averages = match rule.condition {
+        Condition::Cross(fast, slow) => Some((
+            indicators::sma(&closes, fast),
+            indicators::sma(&closes, slow),
+        )),
+        _ => None,
+    };
+
+    let mut alerts = vec![];
+    for j in first.max(1)..closes.len() {
+        let (close, previous) = (closes[j], closes[j - 1]);
+        let message = match rule.condition {
+            Condition::Above(level) if close > level && previous <= level => {
+                Some(format!("crossed above {}", level))
+            }
+            Condition::Below(level) if close < level && previous >= level => {
+                Some(format!("crossed below {}", level))
+            }
+            Condition::Move(percent) => {
+                let change = (close / previous - 1.0) * 100.0;
+                if change.abs() >= percent {
+                    Some(format!("moved {:+.2}%", change))
+                } else {
+                    None
+                }
+            }
+            Condition::High(n) if j >= n => {
+                if closes[j - n..j].iter().all(|c| close > *c) {
+                    Some(format!("new {} day high", n))
+                } else {
+                    None
+                }
+            }
+            Condition::Low(n) if j >= n => {
+                if closes[j - n..j].iter().all(|c| close < *c) {
+                    Some(format!("new {} day low", n))
+                } else {
+                    None
+                }
+            }
+            Condition::Cross(fast, slow) => {
+                let (f, s) = averages.as_ref().unwrap();
+                match (f[j - 1], s[j - 1], f[j], s[j]) {
+                    (Some(f0), Some(s0), Some(f1), Some(s1)) if f0 <= s0 && f1 > s1 => {
+                        Some(format!("{} day average crossed above {} day", fast, slow))
+                    }
+                    (Some(f0), Some(s0), Some(f1), Some(s1)) if f0 >= s0 && f1 < s1 => {
+                        Some(format!("{} day average crossed below {} day", fast, slow))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(message) = message {
+            alerts.push(Alert {
+                rule: rule.clone(),
+                day: history[j].0,
+                close,
+                message,
+            });
+        }
+    }
+    alerts
+}
+
+/// Delivers triggered alerts somewhere.
+pub trait Notifier {
+    fn notify(&self, alerts: &[Alert]) -> Result<()>;
+}
+
+/// Appends one line per alert to a file.
+pub struct LogFile(pub PathBuf);
+
+impl Notifier for LogFile {
+    fn notify(&self, alerts: &[Alert]) -> Result<()> {
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.0)?;
+        let now = chrono::Local::now().naive_local();
+        for alert in alerts.iter() {
+            writeln!(f, "{} {}", now.format("%Y-%m-%d %H:%M:%S"), alert)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a shell command with the alerts on its standard input, one per line.
+pub struct CommandHook(pub String);
+
+impl Notifier for CommandHook {
+    fn notify(&self, alerts: &[Alert]) -> Result<()> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.0)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        {
+            let stdin = child.stdin.as_mut().unwrap();
+            for alert in alerts.iter() {
+                writeln!(stdin, "{}", alert)?;
+            }
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("Alert command '{}' failed: {}", self.0, status);
+        }
+        Ok(())
+    }
+}
+
+/// Appends one message in mbox format to a local mail spool like `/var/mail/USER`.
+pub struct MailSpool(pub PathBuf);
+
+impl Notifier for MailSpool {
+    fn notify(&self, alerts: &[Alert]) -> Result<()> {
+        let user = std::env::var("USER").unwrap_or_else(|_| "root".to_string());
+        let now = chrono::Local::now();
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.0)?;
+        writeln!(f, "From updater {}", now.format("%a %b %e %H:%M:%S %Y"))?;
+        writeln!(f, "From: updater")?;
+        writeln!(f, "To: {}", user)?;
+        writeln!(f, "Date: {}", now.to_rfc2822())?;
+        writeln!(f, "Subject: {} price alert(s)", alerts.len())?;
+        writeln!(f)?;
+        for alert in alerts.iter() {
+            // Keep lines from being taken as the start of a new message.
+            let line = alert.to_string();
+            if line.starts_with("From ") {
+                write!(f, ">")?;
+            }
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+/// Posts the alerts as JSON to an HTTP endpoint on this machine.
+pub struct Webhook(pub reqwest::Url);
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Webhook> {
+        let url = match reqwest::Url::parse(url) {
+            Ok(url) => url,
+            Err(e) => bail!("Invalid webhook URL {}: {}", url, e),
+        };
+        match url.host_str() {
+            Some("localhost") | Some("127.0.0.1") | Some("[::1]") => Ok(Webhook(url)),
+            _ => bail!("Webhook must be a local endpoint: {}", url),
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl Notifier for Webhook {
+    /// `{"alerts":[{"isin":..,"day":..,"rule":..,"close":..,"message":..},..]}`
+    fn notify(&self, alerts: &[Alert]) -> Result<()> {
+        let body = alerts
+            .iter()
+            .map(|a| {
+                format!(
+                    "{{\"isin\":{},\"day\":\"{}\",\"rule\":{},\"close\":{},\"message\":{}}}",
+                    json_string(&a.rule.isin),
+                    a.day,
+                    json_string(&a.rule.condition.to_string()),
+                    a.close,
+                    json_string(&a.message)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        reqwest::Client::new()
+            .post(self.0.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(format!("{{\"alerts\":[{}]}}", body))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// `log:FILE`, `command:SHELL COMMAND`, `mail:SPOOL FILE` or `webhook:URL`.
+pub fn parse_notifier(s: &str) -> Result<Box<dyn Notifier>> {
+    let (kind, arg) = match s.find(':') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => bail!("Invalid notifier: {}", s),
+    };
+    Ok(match kind {
+        "log" => Box::new(LogFile(arg.into())),
+        "command" => Box::new(CommandHook(arg.to_string())),
+        "mail" => Box::new(MailSpool(arg.into())),
+        "webhook" => Box::new(Webhook::new(arg)?),
+        _ => bail!("Unknown notifier: {}", kind),
+    })
+}
@@ -0,0 +1,26 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+/// Combine the histories of several instruments day by day.
+///
+/// Only days present in all histories are kept. The entries of each day are
+/// in the order of `histories`, and the days are sorted.
+pub fn align<T>(histories: Vec<Vec<(NaiveDate, T)>>) -> Vec<(NaiveDate, Vec<T>)> {
+    let n = histories.len();
+    let mut combined = BTreeMap::new();
+    for (i, history) in histories.into_iter().enumerate() {
+        for (day, e) in history.into_iter() {
+            let entry = combined
+                .entry(day)
+                .or_insert_with(|| (0..n).map(|_| None).collect::<Vec<_>>());
+            entry[i] = Some(e);
+        }
+    }
+
+    combined
+        .into_iter()
+        .filter(|(_, entry)| entry.iter().all(|e| e.is_some()))
+        .map(|(day, entry)| (day, entry.into_iter().map(|e| e.unwrap()).collect()))
+        .collect()
+}
@@ -0,0 +1,193 @@
+//! Search the history for the days most similar to the last ones.
+//!
+//! A day is described by the bars of all instruments, each normalised like
+//! `OHLCX::as_f64_vec`, and a window by its last `window` days in order.
+
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::error_def::*;
+use crate::market::Market;
+use crate::ohlcx::OHLCX;
+
+/// How far apart two windows are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distance {
+    Euclidean,
+    Manhattan,
+    /// One minus the Pearson correlation, ignores level and scale of the moves.
+    Correlation,
+}
+
+impl FromStr for Distance {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Distance> {
+        match s {
+            "euclidean" => Ok(Distance::Euclidean),
+            "manhattan" => Ok(Distance::Manhattan),
+            "correlation" => Ok(Distance::Correlation),
+            _ => bail!("Unknown distance: {}", s),
+        }
+    }
+}
+
+impl Distance {
+    pub fn between(self, a: &[f64], b: &[f64]) -> f64 {
+        match self {
+            Distance::Euclidean => a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f64>()
+                .sqrt(),
+            Distance::Manhattan => a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).sum(),
+            Distance::Correlation => {
+                let n = a.len() as f64;
+                let ma = a.iter().sum::<f64>() / n;
+                let mb = b.iter().sum::<f64>() / n;
+                let mut cov = 0.0;
+                let mut va = 0.0;
+                let mut vb = 0.0;
+                for (x, y) in a.iter().zip(b.iter()) {
+                    cov += (x - ma) * (y - mb);
+                    va += (x - ma) * (x - ma);
+                    vb += (y - mb) * (y - mb);
+                }
+                if va > 0.0 && vb > 0.0 {
+                    1.0 - cov / (va * vb).sqrt()
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+/// The bars of all instruments on common day `t`, relative to their last close.
+/// `None` for the first bar of a history.
+pub fn day_vector(market: &Market, t: usize) -> Option<Vec<f64>> {
+    let mut v = vec![];
+    for i in 0..market.isins.len() {
+        let h = market.history(i, t);
+        if h.len() < 2 {
+            return None;
+        }
+        let x = OHLCX {
+            ohlc: h[h.len() - 1].1.clone(),
+            last_close: h[h.len() - 2].1.close,
+        };
+        v.extend(x.as_f64_vec());
+    }
+    Some(v)
+}
+
+/// A historical window similar to the searched one, and what followed it.
+#[derive(Clone, Debug)]
+pub struct Analog {
+    /// Last common day of the window.
+    pub t: usize,
+    pub day: NaiveDate,
+    pub distance: f64,
+    /// Per instrument the close-to-close return over each horizon after `day`.
+    pub returns: Vec<Vec<f64>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnalogSearch {
+    /// Number of days compared.
+    pub window: usize,
+    /// Number of analogs returned.
+    pub k: usize,
+    /// Common days after a match the returns are reported for.
+    pub horizons: Vec<usize>,
+    pub distance: Distance,
+}
+
+impl AnalogSearch {
+    fn window_vector(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        if t + 1 < self.window {
+            return None;
+        }
+        let mut v = vec![];
+        for s in t + 1 - self.window..=t {
+            v.extend(day_vector(market, s)?);
+        }
+        Some(v)
+    }
+
+    /// The `k` windows closest to the one ending on common day `t`.
+    ///
+    /// Only windows whose outcome over all horizons was known on day `t`, and which
+    /// do not overlap the searched window, are candidates. Matches closer than
+    /// `window` days to a better one are skipped, so one episode is not reported
+    /// several times.
+    pub fn search(&self, market: &Market, t: usize) -> Result<Vec<Analog>> {
+        if self.window == 0 {
+            bail!("Analog search needs a window of at least one day");
+        }
+        let query = match self.window_vector(market, t) {
+            Some(v) => v,
+            None => bail!("Not enough history for a window of {} days", self.window),
+        };
+        let gap = self
+            .window
+            .max(self.horizons.iter().cloned().max().unwrap_or(0));
+        let mut candidates = (0..(t + 1).saturating_sub(gap))
+            .filter_map(|s| {
+                self.window_vector(market, s)
+                    .map(|v| (s, self.distance.between(&query, &v)))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut chosen: Vec<(usize, f64)> = vec![];
+        for (s, d) in candidates.into_iter() {
+            if chosen.len() >= self.k {
+                break;
+            }
+            if chosen.iter().all(|(c, _)| c.abs_diff(s) >= self.window) {
+                chosen.push((s, d));
+            }
+        }
+        Ok(chosen
+            .into_iter()
+            .map(|(s, distance)| Analog {
+                t: s,
+                day: market.day(s),
+                distance,
+                returns: (0..market.isins.len())
+                    .map(|i| {
+                        self.horizons
+                            .iter()
+                            .map(|h| market.bar(i, s + h).close / market.bar(i, s).close - 1.0)
+                            .collect()
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+/// Per instrument and horizon the mean return after the analogs.
+pub fn mean_returns(analogs: &[Analog]) -> Vec<Vec<f64>> {
+    let n = analogs.len().max(1) as f64;
+    let mut mean = match analogs.first() {
+        Some(a) => a
+            .returns
+            .iter()
+            .map(|r| vec![0.0; r.len()])
+            .collect::<Vec<_>>(),
+        None => return vec![],
+    };
+    for a in analogs.iter() {
+        for (m, r) in mean.iter_mut().zip(a.returns.iter()) {
+            for (m, r) in m.iter_mut().zip(r.iter()) {
+                *m += r / n;
+            }
+        }
+    }
+    mean
+}
@@ -0,0 +1,309 @@
+//! Replay daily bars through a trading strategy.
+//!
+//! After the close of every common day the strategy sees the market up to that day
+//! and its portfolio, and places day orders. They are executed against the bar of
+//! the next common day, starting at its open.
+
+use std::io::Write;
+
+use chrono::NaiveDate;
+
+use crate::error_def::*;
+use crate::indicators;
+use crate::market::Market;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderKind {
+    /// At the next open.
+    Market,
+    /// At the limit or better: buys at or below, sells at or above it.
+    Limit(f64),
+    /// Once the price reaches the stop: buys at or above, sells at or below it.
+    Stop(f64),
+}
+
+/// Buy a positive, sell a negative `quantity` of an instrument of the market.
+#[derive(Clone, Debug)]
+pub struct Order {
+    pub instrument: usize,
+    pub quantity: f64,
+    pub kind: OrderKind,
+}
+
+/// Trading costs of every fill.
+#[derive(Clone, Debug)]
+pub struct Costs {
+    /// Fixed fee per fill.
+    pub fee: f64,
+    /// Fee as fraction of the traded value.
+    pub fee_rate: f64,
+    /// Market and stop fills are this fraction worse than the price reached.
+    pub slippage: f64,
+}
+
+impl Default for Costs {
+    fn default() -> Costs {
+        Costs {
+            fee: 0.0,
+            fee_rate: 0.0,
+            slippage: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Portfolio {
+    pub cash: f64,
+    /// Quantity held per instrument, negative when short.
+    pub positions: Vec<f64>,
+}
+
+impl Portfolio {
+    /// Cash plus the positions at the close of common day `t`.
+    pub fn value(&self, market: &Market, t: usize) -> f64 {
+        self.cash
+            + self
+                .positions
+                .iter()
+                .enumerate()
+                .map(|(i, q)| q * market.bar(i, t).close)
+                .sum::<f64>()
+    }
+}
+
+/// Decides the orders for the next day.
+pub trait Strategy {
+    /// Called after the close of common day `t`. The orders are valid on day `t + 1` only.
+    fn on_bar(&mut self, market: &Market, t: usize, portfolio: &Portfolio) -> Vec<Order>;
+}
+
+/// An executed order.
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub day: NaiveDate,
+    pub instrument: usize,
+    pub quantity: f64,
+    pub price: f64,
+    pub fee: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct BacktestResult {
+    /// Portfolio value at every close, starting with the first day.
+    pub equity: Vec<(NaiveDate, f64)>,
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Statistics {
+    pub total_return: f64,
+    /// Compound annual growth rate over the calendar days of the equity curve.
+    pub cagr: f64,
+    /// Annualised mean over standard deviation of the daily returns, without risk free rate.
+    pub sharpe: f64,
+    /// Largest fall from a previous high, as a fraction of that high.
+    pub max_drawdown: f64,
+    pub trades: usize,
+}
+
+/// Trading days per year to annualise daily figures.
+pub const TRADING_DAYS: f64 = 252.0;
+
+#[derive(Clone, Debug)]
+pub struct Backtest {
+    pub initial_cash: f64,
+    pub costs: Costs,
+}
+
+impl Backtest {
+    /// The price an order is filled at on the bar of instrument `i` and day `t`, if at all.
+    fn fill_price(&self, market: &Market, t: usize, order: &Order) -> Option<f64> {
+        let bar = market.bar(order.instrument, t);
+        let buy = order.quantity > 0.0;
+        let slip = if buy {
+            1.0 + self.costs.slippage
+        } else {
+            1.0 - self.costs.slippage
+        };
+        match order.kind {
+            OrderKind::Market => Some(bar.open * slip),
+            OrderKind::Limit(limit) => {
+                if buy && bar.open <= limit || !buy && bar.open >= limit {
+                    Some(bar.open)
+                } else if buy && bar.low <= limit || !buy && bar.high >= limit {
+                    Some(limit)
+                } else {
+                    None
+                }
+            }
+            OrderKind::Stop(stop) => {
+                if buy && bar.open >= stop || !buy && bar.open <= stop {
+                    Some(bar.open * slip)
+                } else if buy && bar.high >= stop || !buy && bar.low <= stop {
+                    Some(stop * slip)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn run(&self, market: &Market, strategy: &mut dyn Strategy) -> BacktestResult {
+        let mut portfolio = Portfolio {
+            cash: self.initial_cash,
+            positions: vec![0.0; market.isins.len()],
+        };
+        let mut equity = vec![];
+        let mut trades = vec![];
+        let mut orders: Vec<Order> = vec![];
+        for t in 0..market.days.len() {
+            for order in orders.drain(..) {
+                if order.quantity == 0.0 {
+                    continue;
+                }
+                if let Some(price) = self.fill_price(market, t, &order) {
+                    let fee = self.costs.fee + self.costs.fee_rate * (order.quantity * price).abs();
+                    portfolio.cash -= order.quantity * price + fee;
+                    portfolio.positions[order.instrument] += order.quantity;
+                    trades.push(Trade {
+                        day: market.day(t),
+                        instrument: order.instrument,
+                        quantity: order.quantity,
+                        price,
+                        fee,
+                    });
+                }
+            }
+            equity.push((market.day(t), portfolio.value(market, t)));
+            orders = strategy.on_bar(market, t, &portfolio);
+        }
+        BacktestResult { equity, trades }
+    }
+}
+
+pub fn statistics(result: &BacktestResult) -> Statistics {
+    let equity = &result.equity;
+    let (first, last) = match (equity.first(), equity.last()) {
+        (Some(first), Some(last)) if first.1 > 0.0 => (first, last),
+        _ => {
+            return Statistics {
+                total_return: 0.0,
+                cagr: 0.0,
+                sharpe: 0.0,
+                max_drawdown: 0.0,
+                trades: result.trades.len(),
+            }
+        }
+    };
+    let total_return = last.1 / first.1 - 1.0;
+    let years = (last.0 - first.0).num_days() as f64 / 365.25;
+    let cagr = if years > 0.0 && last.1 > 0.0 {
+        (last.1 / first.1).powf(1.0 / years) - 1.0
+    } else {
+        0.0
+    };
+
+    let returns = equity
+        .windows(2)
+        .map(|w| w[1].1 / w[0].1 - 1.0)
+        .collect::<Vec<_>>();
+    let n = returns.len().max(1) as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let sd = (returns.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / n).sqrt();
+    let sharpe = if sd > 0.0 {
+        mean / sd * TRADING_DAYS.sqrt()
+    } else {
+        0.0
+    };
+
+    let mut peak = first.1;
+    let mut max_drawdown = 0.0;
+    for (_, v) in equity.iter() {
+        peak = f64::max(peak, *v);
+        max_drawdown = f64::max(max_drawdown, (peak - v) / peak);
+    }
+    Statistics {
+        total_return,
+        cagr,
+        sharpe,
+        max_drawdown,
+        trades: result.trades.len(),
+    }
+}
+
+/// `day,equity` per line.
+pub fn write_equity<W: Write>(w: W, result: &BacktestResult) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    w.write_record(["day", "equity"])?;
+    for (day, v) in result.equity.iter() {
+        w.write_record(&[day.to_string(), v.to_string()])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// `day,isin,quantity,price,fee` per line.
+pub fn write_trades<W: Write>(w: W, market: &Market, result: &BacktestResult) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    w.write_record(["day", "isin", "quantity", "price", "fee"])?;
+    for trade in result.trades.iter() {
+        w.write_record(&[
+            trade.day.to_string(),
+            market.isins[trade.instrument].clone(),
+            trade.quantity.to_string(),
+            trade.price.to_string(),
+            trade.fee.to_string(),
+        ])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Hold one instrument while its `fast` simple moving average of the close is above
+/// the `slow` one, investing all cash at the next open, otherwise stay flat.
+pub struct SmaCross {
+    pub instrument: usize,
+    pub fast: usize,
+    pub slow: usize,
+}
+
+impl Strategy for SmaCross {
+    fn on_bar(&mut self, market: &Market, t: usize, portfolio: &Portfolio) -> Vec<Order> {
+        let closes = market
+            .history(self.instrument, t)
+            .iter()
+            .map(|e| e.1.close)
+            .collect::<Vec<_>>();
+        let fast = indicators::sma(&closes, self.fast)
+            .last()
+            .cloned()
+            .unwrap_or(None);
+        let slow = indicators::sma(&closes, self.slow)
+            .last()
+            .cloned()
+            .unwrap_or(None);
+        let held = portfolio.positions[self.instrument];
+        match (fast, slow) {
+            (Some(fast), Some(slow)) if fast > slow && held == 0.0 => {
+                let close = closes[closes.len() - 1];
+                // Leave room for an open above the close.
+                let quantity = (portfolio.cash * 0.98 / close).floor();
+                if quantity > 0.0 {
+                    vec![Order {
+                        instrument: self.instrument,
+                        quantity,
+                        kind: OrderKind::Market,
+                    }]
+                } else {
+                    vec![]
+                }
+            }
+            (Some(fast), Some(slow)) if fast <= slow && held != 0.0 => vec![Order {
+                instrument: self.instrument,
+                quantity: -held,
+                kind: OrderKind::Market,
+            }],
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::backtest::{statistics, write_equity, write_trades, Backtest, Costs, SmaCross};
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::market::Market;
+use updater::store::open_store_or_home;
+
+const USAGE: &str =
+    "usage: backtest [--store DIR|DB] [--cash AMOUNT] [--fee AMOUNT] [--fee-rate RATE]
+                [--slippage RATE] [--sma FAST SLOW] [--equity FILE.csv] [--trades FILE.csv] ISIN
+
+Replays the daily bars of ISIN through a moving average crossover strategy
+(default 20/50 days), orders being executed at the next open.";
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut backtest = Backtest {
+        initial_cash: 100_000.0,
+        costs: Costs::default(),
+    };
+    let mut fast = 20;
+    let mut slow = 50;
+    let mut equity_path = None;
+    let mut trades_path = None;
+    let mut isin = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--cash" => backtest.initial_cash = next_arg(&mut args, USAGE)?.parse()?,
+            "--fee" => backtest.costs.fee = next_arg(&mut args, USAGE)?.parse()?,
+            "--fee-rate" => backtest.costs.fee_rate = next_arg(&mut args, USAGE)?.parse()?,
+            "--slippage" => backtest.costs.slippage = next_arg(&mut args, USAGE)?.parse()?,
+            "--sma" => {
+                fast = next_arg(&mut args, USAGE)?.parse()?;
+                slow = next_arg(&mut args, USAGE)?.parse()?;
+            }
+            "--equity" => equity_path = Some(next_arg(&mut args, USAGE)?),
+            "--trades" => trades_path = Some(next_arg(&mut args, USAGE)?),
+            _ if arg.starts_with("--") || isin.is_some() => bail!(USAGE),
+            _ => isin = Some(arg),
+        }
+    }
+    let isin = match isin {
+        Some(isin) => isin,
+        None => bail!(USAGE),
+    };
+    let store = open_store_or_home(store_path.as_deref())?;
+
+    let market = Market::load(store.as_ref(), std::slice::from_ref(&isin))?;
+    if market.days.is_empty() {
+        bail!("No history for {}", isin);
+    }
+    let mut strategy = SmaCross {
+        instrument: 0,
+        fast,
+        slow,
+    };
+    let result = backtest.run(&market, &mut strategy);
+    let stats = statistics(&result);
+    println!(
+        "{} sma {}/{} {}..{}",
+        isin,
+        fast,
+        slow,
+        market.day(0),
+        market.day(market.days.len() - 1)
+    );
+    println!("final equity  {:>12.2}", result.equity.last().unwrap().1);
+    println!("total return  {:>12.4}", stats.total_return);
+    println!("CAGR          {:>12.4}", stats.cagr);
+    println!("Sharpe        {:>12.4}", stats.sharpe);
+    println!("max drawdown  {:>12.4}", stats.max_drawdown);
+    println!("trades        {:>12}", stats.trades);
+
+    if let Some(path) = equity_path {
+        write_equity(File::create(&path)?, &result)?;
+    }
+    if let Some(path) = trades_path {
+        write_trades(File::create(&path)?, &market, &result)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -1,232 +1,346 @@
-use std::collections::HashMap;
-use std::fmt;
-use std::fs::File;
+use std::path::PathBuf;
 
 //use log::*;
-use chrono::offset::{Local, TimeZone};
-use chrono::Date;
-use chrono::{Datelike, NaiveDate};
-use ndarray::s;
-use ndarray::Array2;
-use plotters::prelude::*;
+use error_chain::bail;
 
+use updater::analog::{mean_returns, AnalogSearch, Distance};
+use updater::chart::{ChartStyle, PriceChart};
+use updater::cli::{next_arg, run_main};
+use updater::cluster::Method;
 use updater::error_def::*;
-use updater::ohlc::OHLC;
+use updater::features::{Pipeline, DEFAULT_PIPELINE};
+use updater::forecast::{forecast, write_report, DEFAULT_QUANTILES};
+use updater::market::{read_isins, Market};
+use updater::markov::{state_means, MarkovSettings, TransitionModel};
+use updater::model::Model;
+use updater::node_stats::{self, node_stats, DEFAULT_HORIZONS};
+use updater::som::seeded_rng;
+use updater::som_plot::{plot_component_planes, plot_hits, plot_transitions, plot_u_matrix};
+use updater::store::open_store_or_home;
+use updater::walkforward::{evaluate, WalkForward};
 
-struct OHLCX {
-    ohlc: OHLC,
-    last_close: f32,
-}
-impl OHLCX {
-    fn as_f64_vec(&self) -> Vec<f64> {
-        let lc = self.last_close;
-        vec![
-            ((self.ohlc.open / lc - 1.0) * 20.0) as f64,
-            ((self.ohlc.high / lc - 1.0) * 20.0) as f64,
-            ((self.ohlc.low / lc - 1.0) * 20.0) as f64,
-            ((self.ohlc.close / lc - 1.0) * 20.0) as f64,
-        ]
-    }
-}
-impl fmt::Debug for OHLCX {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "OHLCX({}, last={})", self.ohlc, self.last_close)
-    }
-}
-fn as_f64_vec(day: &NaiveDate) -> Vec<f64> {
-    match day.weekday() {
-        chrono::Weekday::Mon => vec![1.0, 0.0, 0.0, 0.0, 0.0],
-        chrono::Weekday::Tue => vec![0.0, 1.0, 0.0, 0.0, 0.0],
-        chrono::Weekday::Wed => vec![0.0, 0.0, 1.0, 0.0, 0.0],
-        chrono::Weekday::Thu => vec![0.0, 0.0, 0.0, 1.0, 0.0],
-        chrono::Weekday::Fri => vec![0.0, 0.0, 0.0, 0.0, 1.0],
-        chrono::Weekday::Sat => vec![0.0, 0.0, 0.0, 0.0, 0.0],
-        chrono::Weekday::Sun => vec![0.0, 0.0, 0.0, 0.0, 0.0],
-    }
-}
+const USAGE: &str = "usage: boerse [--store DIR|DB] [--isins FILE] [--features SPEC|FILE]
+              [--method som:RxC|kmeans:K|gmm:K ...] [--iterations N]
+              [--seed N] [--save MODEL | --load MODEL]
+              [--order N] [--smoothing none|laplace[:A]|kn[:D]] [--radius R] [--min-count N]
+              [--report FILE.{csv,json}] [--plots DIR] [--node-stats FILE.csv]
+              [--chart FILE.{png,svg,gif}]
+              [--analogs K [--analog-window N] [--distance euclidean|manhattan|correlation]]
+              [--backtest WINDOW [--retrain DAYS] [--target COLUMN]] [ISIN ...]
 
-fn load_file(fname: &std::ffi::OsString) -> Result<Vec<(NaiveDate, OHLCX)>> {
-    let f = File::open(fname)?;
-    let ohlc_data = OHLC::load_file(f)?;
-
-    let mut opt_last_close = None;
-    let mut ohlc_x_data = vec![];
-    for (day, e) in ohlc_data.into_iter() {
-        if let Some(last_close) = opt_last_close {
-            let o_x = OHLCX {
-                ohlc: e.clone(),
-                last_close,
-            };
-            ohlc_x_data.push((day, o_x));
-        }
-        opt_last_close = Some(e.close);
-    }
-    Ok(ohlc_x_data)
-}
+The ISINs to analyse are given on the command line or in FILE, one per line.
+Without either, the DAX (DE0008469008) and Dow Jones (US2605661048) are used.
+The input vectors are built by a feature pipeline, by default \"weekday; bar(*) scale(20)\".
+They are clustered by a 15x15 SOM unless another --method is given; with --backtest
+several methods can be given to compare them.
+A trained model can be saved and later loaded instead of training again;
+it brings its own ISINs and features.
+The next winner is predicted from the last N winners (default 1), backing off to
+fewer winners for contexts seen less than --min-count times, with the counts of
+contexts within R grid steps pooled (default 0).
+The next bar of every instrument with a bar feature is printed in prices, with
+quantile bands from the days of the predicted nodes, and can be written as a report.
+--plots renders the U-matrix, hit map, component planes and transition graph of a SOM.
+--chart draws the candles of the first instrument, 100 days per frame in a GIF;
+the chart command offers more options.
+--node-stats writes per node the member days and the returns of the first instrument
+over the next day and week; those of the node of the last day are printed.
+--analogs lists the K historical windows of N days (default 5) most similar to the
+last N days, and the returns of all instruments over the next day and week after them.
+--backtest predicts every day out of sample from a model trained on the WINDOW days
+before, retrained every DAYS days (default 20), and scores the predicted feature
+COLUMN (default the first close) against naive baselines.";
 
-fn main() -> Result<()> {
-    simple_logger::init().unwrap();
-
-    let home_path = dirs::home_dir().unwrap();
-    let mut fname = home_path.clone();
-    fname.push("data");
-    fname.push("stock");
-    fname.push("DE0008469008");
-    fname.push("ohlc.csv");
-    let dax = load_file(&fname.into_os_string())?;
-
-    let dx = dax
-        .iter()
-        .map(|(d, e)| (chrono::Local.from_utc_date(&d) as Date<Local>, e))
-        .collect::<Vec<_>>();
-    {
-        //let root = BitMapBackend::new("dax.png", (1024, 768)).into_drawing_area();
-        let root = BitMapBackend::gif("dax.gif", (1024, 768), 1000)
-            .unwrap()
-            .into_drawing_area(); // 1000*1ms
-        let mut remain = dx.clone();
-        while remain.len() > 100 {
-            let mut part = remain;
-            remain = part.split_off(100);
-            root.fill(&WHITE).unwrap();
-            let from_date = part.first().unwrap().0;
-            let to_date = part.last().unwrap().0;
-            let from_y = part.iter().map(|e| e.1.ohlc.low).fold(1. / 0., f32::min);
-            let to_y = part.iter().map(|e| e.1.ohlc.high).fold(0. / 0., f32::max);
-            println!("{}", from_date);
-            let mut chart = ChartBuilder::on(&root)
-                .x_label_area_size(60)
-                .y_label_area_size(60)
-                .caption("DAX", ("Arial", 50.0).into_font())
-                .build_ranged(from_date..to_date, from_y..to_y)
-                .unwrap();
-
-            chart
-                .configure_mesh()
-                .line_style_2(&WHITE)
-                .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
-                .draw()
-                .unwrap();
-
-            chart
-                .draw_series(part.into_iter().map(|(d, x)| {
-                    CandleStick::new(
-                        d,
-                        x.ohlc.open,
-                        x.ohlc.high,
-                        x.ohlc.low,
-                        x.ohlc.close,
-                        &GREEN,
-                        &RED,
-                        15,
-                    )
-                }))
-                .unwrap();
-            root.present().unwrap();
+const DEFAULT_METHOD: Method = Method::Som(15, 15);
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut isins = vec![];
+    let mut feature_spec = DEFAULT_PIPELINE.to_string();
+    let mut methods = vec![];
+    let mut iterations = None;
+    let mut seed = None;
+    let mut save_path = None;
+    let mut load_path = None;
+    let mut backtest = None;
+    let mut retrain = 20;
+    let mut target = None;
+    let mut markov = MarkovSettings::default();
+    let mut report_path = None;
+    let mut plot_dir = None;
+    let mut stats_path = None;
+    let mut chart_path = None;
+    let mut analogs = None;
+    let mut analog_window = 5;
+    let mut distance = Distance::Euclidean;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--isins" => isins.extend(read_isins(&next_arg(&mut args, USAGE)?)?),
+            "--features" => {
+                let spec = next_arg(&mut args, USAGE)?;
+                feature_spec = std::fs::read_to_string(&spec).unwrap_or(spec);
+            }
+            "--method" => methods.push(next_arg(&mut args, USAGE)?.parse::<Method>()?),
+            "--iterations" => iterations = Some(next_arg(&mut args, USAGE)?.parse()?),
+            "--seed" => seed = Some(next_arg(&mut args, USAGE)?.parse()?),
+            "--save" => save_path = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--load" => load_path = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--report" => report_path = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--plots" => plot_dir = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--chart" => chart_path = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--node-stats" => stats_path = Some(PathBuf::from(next_arg(&mut args, USAGE)?)),
+            "--analogs" => analogs = Some(next_arg(&mut args, USAGE)?.parse()?),
+            "--analog-window" => analog_window = next_arg(&mut args, USAGE)?.parse()?,
+            "--distance" => distance = next_arg(&mut args, USAGE)?.parse()?,
+            "--backtest" => backtest = Some(next_arg(&mut args, USAGE)?.parse()?),
+            "--retrain" => retrain = next_arg(&mut args, USAGE)?.parse()?,
+            "--target" => target = Some(next_arg(&mut args, USAGE)?),
+            "--order" => markov.order = next_arg(&mut args, USAGE)?.parse()?,
+            "--smoothing" => markov.smoothing = next_arg(&mut args, USAGE)?.parse()?,
+            "--radius" => markov.radius = next_arg(&mut args, USAGE)?.parse()?,
+            "--min-count" => markov.min_count = next_arg(&mut args, USAGE)?.parse()?,
+            _ if arg.starts_with("--") => bail!(USAGE),
+            _ => isins.push(arg),
         }
     }
-
-    let mut fname = home_path.clone();
-    fname.push("data");
-    fname.push("stock");
-    fname.push("US2605661048");
-    fname.push("ohlc.csv");
-    let dow = load_file(&fname.into_os_string())?;
-
-    println!("dax=#{} dow=#{}", dax.len(), dow.len());
-
-    println!("Last= {:?}", dax.last());
-    println!("Last= {:?}", dow.last());
-
-    let mut combined = HashMap::new();
-    for (day, ohlc) in dax.into_iter() {
-        let entry = combined.entry(day);
-        let entry = entry.or_insert(vec![None, None]);
-        entry[0] = Some(ohlc);
+    let model = match load_path {
+        Some(path) => Some(Model::load(&path)?),
+        None => None,
+    };
+    if let Some(ref model) = model {
+        isins = model.isins.clone();
+        feature_spec = model.features.clone();
     }
-    for (day, ohlc) in dow.into_iter() {
-        let entry = combined.entry(day);
-        let entry = entry.or_insert(vec![None, None]);
-        entry[1] = Some(ohlc);
+    if methods.is_empty() {
+        methods.push(DEFAULT_METHOD);
     }
+    if isins.is_empty() {
+        isins = vec!["DE0008469008".to_string(), "US2605661048".to_string()];
+    }
+    let store = open_store_or_home(store_path.as_deref())?;
 
-    combined.retain(|_, entry| entry.iter().filter(|e| e.is_some()).count() == entry.len());
+    let market = Market::load(store.as_ref(), &isins)?;
+    if market.days.is_empty() {
+        bail!("No common days of {:?}", isins);
+    }
+    for (isin, history) in isins.iter().zip(market.histories.iter()) {
+        println!("{}=#{} Last= {:?}", isin, history.len(), history.last());
+    }
 
-    let mut combined = combined.into_iter().collect::<Vec<_>>();
-    combined.sort_by_key(|(day, _)| *day);
+    if let Some(path) = chart_path {
+        let history = &market.histories[0];
+        let chart = PriceChart {
+            title: isins[0].clone(),
+            style: ChartStyle::Candle,
+            log_scale: false,
+            overlays: vec![],
+            volume: true,
+            indicators: vec![],
+        };
+        chart.plot(&path, (1024, 768), history, 0..history.len(), 100)?;
+    }
 
-    println!("combined=#{}", combined.len());
-    println!("Last= {:?}", combined.last());
+    println!("combined=#{}", market.days.len());
+    println!("Last= {:?}", market.days.last());
 
-    let mut rows = 0;
-    let mut data = vec![];
-    for (day, entries) in &combined {
-        rows += 1;
-        data.extend(as_f64_vec(day));
-        for ohlc in entries {
-            data.extend(ohlc.as_ref().unwrap().as_f64_vec());
+    if let Some(k) = analogs {
+        let search = AnalogSearch {
+            window: analog_window,
+            k,
+            horizons: DEFAULT_HORIZONS.to_vec(),
+            distance,
+        };
+        let found = search.search(&market, market.days.len() - 1)?;
+        println!(
+            "{} analogs of the last {} days:",
+            found.len(),
+            analog_window
+        );
+        for a in found.iter() {
+            println!(
+                "  {} distance={:.4} returns={:+.4?}",
+                a.day, a.distance, a.returns
+            );
         }
+        println!("  mean returns={:+.4?}", mean_returns(&found));
     }
 
-    let inputs = data.len() / rows;
-    let data = Array2::<f64>::from_shape_vec((rows, inputs), data).unwrap();
+    let mut pipeline = Pipeline::parse(&feature_spec, &market)?;
+    let inputs = pipeline.width();
+    println!("features={:?}", pipeline.names());
 
-    let mut som = rusticsom::SOM::create(15, 15, inputs, true, None, None, None, None);
-    som.train_random(data.clone(), 2000);
+    if let Some(window) = backtest {
+        let col = match target {
+            Some(ref name) => pipeline.names().iter().position(|n| n == name),
+            None => pipeline.names().iter().position(|n| n.ends_with(".close")),
+        };
+        let col = match col {
+            Some(col) => col,
+            None => bail!("No target column {:?} in {:?}", target, pipeline.names()),
+        };
+        let seed = seed.unwrap_or_else(rand::random);
+        for method in methods.iter() {
+            let wf = WalkForward {
+                window,
+                retrain,
+                method: *method,
+                iterations: iterations.unwrap_or_else(|| method.default_iterations()),
+                seed,
+                markov: markov.clone(),
+            };
+            let predictions = wf.run(&market, &mut pipeline)?;
+            println!(
+                "walk-forward {} window={} retrain={} seed={} target={} predictions={}",
+                method,
+                window,
+                retrain,
+                seed,
+                pipeline.names()[col],
+                predictions.len()
+            );
+            if let (Some(first), Some(last)) = (predictions.first(), predictions.last()) {
+                println!("predicted {}..{}", first.day, last.day);
+            }
+            println!("{:<20} {:>8} {:>12}", "model", "hit rate", "MAE");
+            let name = format!("{}+markov", method);
+            for score in evaluate(&name, &predictions, col).iter() {
+                match score.mean_absolute_error {
+                    Some(mae) => {
+                        println!("{:<20} {:>8.3} {:>12.6}", score.name, score.hit_rate, mae)
+                    }
+                    None => println!("{:<20} {:>8.3} {:>12}", score.name, score.hit_rate, "-"),
+                }
+            }
+        }
+        return Ok(());
+    }
 
-    let mut winners = vec![];
-    let mut avg = HashMap::new();
-    for v in data.outer_iter() {
-        let winner = som.winner(v.to_owned());
-        //println!("{:?} {:?}",v,winner);
-        winners.push(winner);
+    let (days, rows): (Vec<_>, Vec<_>) = pipeline.raw_rows(&market).into_iter().unzip();
+    let (data, clusterer) = match model {
+        Some(model) => {
+            println!(
+                "model trained {}..{} seed={}",
+                model.train_from, model.train_to, model.seed
+            );
+            pipeline.set_params(model.params)?;
+            (pipeline.transform(&rows), model.clusterer)
+        }
+        None => {
+            pipeline.fit(&rows);
+            let data = pipeline.transform(&rows);
+            let seed = seed.unwrap_or_else(rand::random);
+            println!("seed={}", seed);
+            let mut rng = seeded_rng(seed);
+            let method = methods[0];
+            let iterations = iterations.unwrap_or_else(|| method.default_iterations());
+            let mut clusterer = method.build(inputs, &mut rng);
+            clusterer.fit(&data, iterations, &mut rng);
+            if let Some(path) = save_path {
+                let model = Model {
+                    clusterer,
+                    isins: isins.clone(),
+                    features: feature_spec.clone(),
+                    params: pipeline.params().to_vec(),
+                    train_from: market.day(days[0]),
+                    train_to: market.day(*days.last().unwrap()),
+                    seed,
+                    iterations,
+                };
+                model.save(&path)?;
+                println!("model saved to {:?}", path);
+                (data, model.clusterer)
+            } else {
+                (data, clusterer)
+            }
+        }
+    };
+    println!(
+        "scoring {}..{}",
+        market.day(days[0]),
+        market.day(*days.last().unwrap())
+    );
 
-        let e0 = avg.entry(winner);
-        let e0 = e0.or_insert(vec![]);
-        e0.push(v);
+    let winners = clusterer.assign_all(&data);
+    if let Some(som) = clusterer.as_som() {
+        println!("{:?}", som.activation_response(&data));
+    }
+    if let Some(dir) = plot_dir {
+        let som = match clusterer.as_som() {
+            Some(som) => som,
+            None => bail!("Plots need a SOM, not {}", clusterer.kind()),
+        };
+        std::fs::create_dir_all(&dir)?;
+        plot_u_matrix(som, &dir.join("u_matrix.png"))?;
+        plot_hits(som, &data, &dir.join("hits.png"))?;
+        plot_component_planes(som, pipeline.names(), &dir.join("components.png"))?;
+        plot_transitions(som, &winners, &dir.join("transitions.png"))?;
+        println!("plots written to {:?}", dir);
     }
 
-    println!("{:?}", som.activation_response());
-
-    let mut markov = HashMap::new();
-    for i in 1..winners.len() {
-        let w0 = &winners[i - 1];
-        let w1 = &winners[i];
+    let stats = node_stats(&market, 0, &days, &winners, &DEFAULT_HORIZONS);
+    let last_node = winners.last().unwrap();
+    if let Some(s) = stats.iter().find(|s| s.node == *last_node) {
+        println!(
+            "{} days like the last one at {:?}, {} returns:",
+            s.days.len(),
+            s.node,
+            isins[0]
+        );
+        for r in s.returns.iter() {
+            println!(
+                "  {:>2} days: n={} mean={:+.4} median={:+.4} vol={:.4} up={:.2} best={:+.4} ({}) worst={:+.4} ({})",
+                r.horizon, r.n, r.mean, r.median, r.volatility, r.up, r.best.1, r.best.0, r.worst.1, r.worst.0
+            );
+        }
+    }
+    if let Some(path) = stats_path {
+        node_stats::write_csv(std::fs::File::create(&path)?, &stats)?;
+        println!("node statistics written to {:?}", path);
+    }
 
-        let e0 = markov.entry(w0);
-        let e0 = e0.or_insert(HashMap::new());
-        let e1 = e0.entry(w1);
-        let e1 = e1.or_insert(0);
-        *e1 += 1;
+    let transitions = TransitionModel::fit(&winners, &markov);
+    let means = state_means(&winners, &data);
+    let distribution = transitions.distribution(&winners);
+    for (node, p) in distribution.iter().take(10) {
+        println!(
+            "{:?} {:.3} => {:?}",
+            node,
+            p,
+            pipeline.denormalise(&means[node].to_vec())
+        );
     }
 
-    let w_last = winners.last().unwrap();
-    if let Some(hm) = markov.get(w_last) {
-        let mut est = ndarray::Array1::<f64>::zeros(inputs);
-        let mut est_cnt = 0;
-        for (wp, cnt) in hm.iter() {
-            print!("{:?} {}", wp, cnt);
-            if let Some(avg_vec) = avg.get(wp) {
-                let mut av = ndarray::Array1::<f64>::zeros(inputs);
-                for ax in avg_vec.iter() {
-                    av = av + ax;
-                }
-                av /= avg_vec.len() as f64;
-                let mut avx = av.slice_mut(s![5..]);
-                avx /= 20.0;
-                avx += 1.0;
-                //avx *= last_close;
-                println!(" => {}", av);
-                if *cnt > 1 {
-                    av *= *cnt as f64;
-                    est = est + av;
-                    est_cnt += *cnt;
-                }
-            } else {
-                println!(" => None");
-            }
+    let forecasts = forecast(
+        &market,
+        &pipeline,
+        *days.last().unwrap(),
+        &winners,
+        &rows,
+        &distribution,
+        &DEFAULT_QUANTILES,
+    );
+    for f in forecasts.iter() {
+        println!("{} after {} close {:.2}", f.isin, f.day, f.last_close);
+        for field in f.fields.iter() {
+            let bands = f
+                .levels
+                .iter()
+                .zip(field.quantiles.iter())
+                .map(|(q, v)| format!("q{}={:.2}", q, v))
+                .collect::<Vec<_>>();
+            println!(
+                "  {:<5} {:>10.2}  {}",
+                field.field,
+                field.expected,
+                bands.join(" ")
+            );
         }
-        println!("estimate {}", est / est_cnt as f64);
+    }
+    if let Some(path) = report_path {
+        write_report(&path, &forecasts)?;
+        println!("report written to {:?}", path);
     }
 
     //if let Some(tv) = opt_tv {
@@ -236,3 +350,7 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn main() {
+    run_main(run);
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use updater::chart::{plot_series, rebased, relative_strength, ChartStyle, PriceChart};
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::market::Market;
+use updater::store::open_store_or_home;
+
+const USAGE: &str = "usage: chart [--store DIR|DB] [--from DATE] [--to DATE] [--size WxH]
+             [--output FILE.{png,svg,gif}] [--title TEXT] [--style candle|line|ohlc]
+             [--log] [--overlay sma:N|ema:N]... [--indicator rsi:N|macd[:F,S,G]]...
+             [--no-volume] [--frame N] ISIN
+       chart [--store DIR|DB] [--from DATE] [--to DATE] [--size WxH]
+             [--output FILE.{png,svg,gif}] [--rebase | --relative BENCHMARK] ISIN...
+
+Draws the bars of one ISIN from DATE to DATE as candles (default), a line of the
+closes or OHLC bars, titled with the name of the instrument, optionally on a log
+scale and with moving averages. Below the prices are panes of the volume, if the
+store has it, and of the indicators. A GIF shows N bars per frame (default 100).
+With several ISINs or --rebase, draws their closes on the days all of them
+traded, rebased to 100 at the first day from DATE on, or with --relative their
+strength against the BENCHMARK ISIN, i.e. their close divided by that of the
+benchmark, rebased the same way. The chart is written to FILE (default chart.png),
+by default 1024x768.";
+
+fn parse_size(s: &str) -> Result<(u32, u32)> {
+    let mut parts = s.split('x');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(w), Some(h), None) => Ok((w.parse()?, h.parse()?)),
+        _ => bail!("Invalid size: {}", s),
+    }
+}
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut from = None;
+    let mut to = None;
+    let mut rebase = false;
+    let mut benchmark = None;
+    let mut size = (1024, 768);
+    let mut output = PathBuf::from("chart.png");
+    let mut title = None;
+    let mut chart = PriceChart {
+        title: String::new(),
+        style: ChartStyle::Candle,
+        log_scale: false,
+        overlays: vec![],
+        volume: true,
+        indicators: vec![],
+    };
+    let mut frame = 100;
+    let mut isins = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--from" => {
+                from = Some(NaiveDate::parse_from_str(
+                    &next_arg(&mut args, USAGE)?,
+                    "%Y-%m-%d",
+                )?)
+            }
+            "--to" => {
+                to = Some(NaiveDate::parse_from_str(
+                    &next_arg(&mut args, USAGE)?,
+                    "%Y-%m-%d",
+                )?)
+            }
+            "--rebase" => rebase = true,
+            "--relative" => benchmark = Some(next_arg(&mut args, USAGE)?),
+            "--size" => size = parse_size(&next_arg(&mut args, USAGE)?)?,
+            "--output" => output = PathBuf::from(next_arg(&mut args, USAGE)?),
+            "--title" => title = Some(next_arg(&mut args, USAGE)?),
+            "--style" => chart.style = next_arg(&mut args, USAGE)?.parse()?,
+            "--log" => chart.log_scale = true,
+            "--overlay" => chart.overlays.push(next_arg(&mut args, USAGE)?.parse()?),
+            "--indicator" => chart.indicators.push(next_arg(&mut args, USAGE)?.parse()?),
+            "--no-volume" => chart.volume = false,
+            "--frame" => frame = next_arg(&mut args, USAGE)?.parse()?,
+            _ if arg.starts_with("--") => bail!(USAGE),
+            _ => isins.push(arg),
+        }
+    }
+    if isins.is_empty() {
+        bail!(USAGE);
+    }
+    let store = open_store_or_home(store_path.as_deref())?;
+
+    if isins.len() == 1 && !rebase && benchmark.is_none() {
+        let isin = &isins[0];
+        let history = store.load(isin)?;
+        let start = match from {
+            Some(from) => history.iter().position(|e| e.0 >= from),
+            None => Some(0),
+        };
+        let end = match to {
+            Some(to) => history
+                .iter()
+                .position(|e| e.0 > to)
+                .unwrap_or(history.len()),
+            None => history.len(),
+        };
+        let start = match start {
+            Some(start) if start + 1 < end => start,
+            _ => bail!("Less than two bars of {} in the range", isin),
+        };
+        chart.title = match title {
+            Some(title) => title,
+            None => match store.load_meta(isin)?.and_then(|m| m.name) {
+                Some(name) => format!("{} ({})", name, isin),
+                None => isin.clone(),
+            },
+        };
+        chart.plot(&output, size, &history, start..end, frame)?;
+        println!("chart written to {:?}", output);
+        return Ok(());
+    }
+
+    if let Some(ref benchmark) = benchmark {
+        if !isins.contains(benchmark) {
+            isins.push(benchmark.clone());
+        }
+    }
+    let mut market = Market::load(store.as_ref(), &isins)?;
+    if let Some(to) = to {
+        market.days.retain(|d| d.0 <= to);
+    }
+    let (default_title, series) = match benchmark {
+        Some(benchmark) => {
+            let b = market.instrument(&benchmark)?;
+            (
+                format!("Relative strength against {}", benchmark),
+                relative_strength(&market, b, from)?,
+            )
+        }
+        None => ("Rebased to 100".to_string(), rebased(&market, from)?),
+    };
+    let title = title.unwrap_or(default_title);
+    plot_series(&output, size, &title, &series)?;
+    println!("chart written to {:?}", output);
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -0,0 +1,23 @@
+use error_chain::bail;
+
+use updater::cli::run_main;
+use updater::error_def::*;
+use updater::store::{copy_store, open_store};
+
+const USAGE: &str = "usage: convert_store FROM TO   (e.g. stock/ stock.db or stock.db stock/)";
+
+fn run() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.len() != 2 {
+        bail!(USAGE);
+    }
+    let from = open_store(&args[0])?;
+    let to = open_store(&args[1])?;
+    let n = copy_store(from.as_ref(), to.as_ref())?;
+    println!("{} instruments copied from {} to {}", n, args[0], args[1]);
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
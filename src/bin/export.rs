@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::columnar;
+use updater::error_def::*;
+use updater::store::{open_store, Store};
+
+const USAGE: &str =
+    "usage: export [--store DIR|DB] [--import] FILE.{parquet,arrow,jsonl} [ISIN ...]
+
+JSON lines need the `serialize` feature.";
+
+fn is_jsonl(fname: &Path) -> bool {
+    fname.extension().and_then(|e| e.to_str()) == Some("jsonl")
+}
+
+fn import_file(store: &dyn Store, fname: &Path) -> Result<Vec<String>> {
+    if is_jsonl(fname) {
+        #[cfg(feature = "serialize")]
+        return updater::jsonl::import(store, fname);
+        #[cfg(not(feature = "serialize"))]
+        bail!(USAGE);
+    }
+    columnar::import(store, fname)
+}
+
+fn export_file(store: &dyn Store, isins: &[String], fname: &Path) -> Result<usize> {
+    if is_jsonl(fname) {
+        #[cfg(feature = "serialize")]
+        return updater::jsonl::export(store, isins, fname);
+        #[cfg(not(feature = "serialize"))]
+        bail!(USAGE);
+    }
+    columnar::export(store, isins, fname)
+}
+
+fn run() -> Result<()> {
+    let mut store_path = "stock/".to_string();
+    let mut import = false;
+    let mut fname = None;
+    let mut isins = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = next_arg(&mut args, USAGE)?,
+            "--import" => import = true,
+            _ if fname.is_none() => fname = Some(PathBuf::from(arg)),
+            _ => isins.push(arg),
+        }
+    }
+    let fname = match fname {
+        Some(fname) => fname,
+        None => bail!(USAGE),
+    };
+
+    let store = open_store(&store_path)?;
+    if import {
+        for isin in import_file(store.as_ref(), &fname)?.iter() {
+            println!("{}", isin);
+        }
+    } else {
+        let bars = export_file(store.as_ref(), &isins, &fname)?;
+        println!("{} bars written to {:?}", bars, fname);
+    }
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
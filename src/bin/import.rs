@@ -0,0 +1,78 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::import::ImportConfig;
+use updater::store::{open_store, ConflictRule};
+
+const USAGE: &str = "usage: import [--store DIR|DB] [--delimiter C] [--no-headers]
+              [--date COL] [--open COL] [--high COL] [--low COL] [--close COL]
+              [--volume COL]
+              [--date-format FMT] [--locale de|en] [--on-conflict replace|keep|fail (default keep)]
+              ISIN FILE
+
+COL is a header name or a column number counting from 0. Without --volume the
+volume is only read from a column named Volume, Volumen or Stück, since columns
+like Umsatz often hold the turnover in money instead.";
+
+fn run() -> Result<()> {
+    let mut store_path = "stock/".to_string();
+    let mut config = ImportConfig::default();
+    let mut rule = ConflictRule::Keep;
+    let mut positional = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--no-headers" {
+            config.has_headers = false;
+            continue;
+        }
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        let value = next_arg(&mut args, USAGE)?;
+        match arg.as_str() {
+            "--store" => store_path = value,
+            "--delimiter" => match value.as_str() {
+                "tab" | "\\t" => config.delimiter = Some(b'\t'),
+                v if v.len() == 1 => config.delimiter = Some(v.as_bytes()[0]),
+                _ => bail!("Delimiter must be one character: {}", value),
+            },
+            "--date" => config.date = Some(value.parse()?),
+            "--open" => config.open = Some(value.parse()?),
+            "--high" => config.high = Some(value.parse()?),
+            "--low" => config.low = Some(value.parse()?),
+            "--close" => config.close = Some(value.parse()?),
+            "--volume" => config.volume = Some(value.parse()?),
+            "--date-format" => config.date_format = Some(value),
+            "--locale" => config.locale = value.parse()?,
+            "--on-conflict" => rule = value.parse()?,
+            _ => bail!(USAGE),
+        }
+    }
+    if positional.len() != 2 {
+        bail!(USAGE);
+    }
+    let isin = &positional[0];
+    let fname = &positional[1];
+
+    let new_ohlc = config.load(File::open(fname)?)?;
+    let n = new_ohlc.len();
+    let store = open_store(&store_path)?;
+    let all_ohlc = store.merge_with(isin, new_ohlc, rule)?;
+    println!(
+        "{} bars read from {}, {} now has {} bars",
+        n,
+        fname,
+        isin,
+        all_ohlc.len()
+    );
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::ledger::{positions, read_transactions, valuation, write_valuation, CostMethod};
+use updater::store::open_store_or_home;
+
+const USAGE: &str =
+    "usage: portfolio [--store DIR|DB] [--cost fifo|average] [--history FILE.csv] TRANSACTIONS.csv
+
+Prints the positions of the transactions with their profit and loss at the last
+close in the store. TRANSACTIONS.csv has the header date,type,isin,quantity,price,fee
+with type buy, sell, dividend or fee. --history writes the daily valuation.";
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut method = CostMethod::Fifo;
+    let mut history_path = None;
+    let mut transactions_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--cost" => method = next_arg(&mut args, USAGE)?.parse()?,
+            "--history" => history_path = Some(next_arg(&mut args, USAGE)?),
+            _ if arg.starts_with("--") || transactions_path.is_some() => bail!(USAGE),
+            _ => transactions_path = Some(arg),
+        }
+    }
+    let transactions_path = match transactions_path {
+        Some(path) => path,
+        None => bail!(USAGE),
+    };
+    let store = open_store_or_home(store_path.as_deref())?;
+
+    let transactions = read_transactions(File::open(&transactions_path)?)?;
+    let positions = positions(&transactions, method, None)?;
+
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12} {:>12} {:>10}",
+        "isin",
+        "quantity",
+        "avg price",
+        "close",
+        "day",
+        "value",
+        "unrealised",
+        "realised",
+        "dividends"
+    );
+    let mut total_value = 0.0;
+    let mut total_unrealised = 0.0;
+    let mut total_realised = 0.0;
+    let mut total_dividends = 0.0;
+    let mut total_fees = 0.0;
+    for p in positions.iter() {
+        let (day, close) = if p.quantity > 0.0 {
+            match store.load(&p.isin)?.last() {
+                Some((day, ohlc)) => (day.to_string(), ohlc.close),
+                None => bail!("No history for {}", p.isin),
+            }
+        } else {
+            (String::new(), 0.0)
+        };
+        let value = p.quantity * close;
+        // Fees of the whole account have no ISIN.
+        if !p.isin.is_empty() {
+            println!(
+                "{:<12} {:>10} {:>10.2} {:>10.2} {:>10} {:>12.2} {:>12.2} {:>12.2} {:>10.2}",
+                p.isin,
+                p.quantity,
+                p.average_price(),
+                close,
+                day,
+                value,
+                p.unrealised(close),
+                p.realised,
+                p.dividends
+            );
+        }
+        total_value += value;
+        total_unrealised += p.unrealised(close);
+        total_realised += p.realised;
+        total_dividends += p.dividends;
+        total_fees += p.fees;
+    }
+    println!("market value  {:>12.2}", total_value);
+    println!("unrealised    {:>12.2}", total_unrealised);
+    println!("realised      {:>12.2}", total_realised);
+    println!("dividends     {:>12.2}", total_dividends);
+    println!("other fees    {:>12.2}", total_fees);
+    println!(
+        "profit        {:>12.2}",
+        total_unrealised + total_realised + total_dividends - total_fees
+    );
+
+    if let Some(path) = history_path {
+        let valuations = valuation(store.as_ref(), &transactions, method)?;
+        write_valuation(File::create(&path)?, &valuations)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -1,10 +1,10 @@
-use std::fs::File;
-//use std::path::PathBuf;
-
-use gdk::EventMask;
-use gtk::Orientation::Horizontal;
+use chrono::{NaiveDate, NaiveDateTime};
+use gdk::enums::key;
+use gdk::{EventMask, ScrollDirection};
+use gtk::Orientation::{Horizontal, Vertical};
 use gtk::{
     //OrientableExt,
+    ButtonExt,
     ContainerExt,
     //BoxExt,
     DrawingArea,
@@ -22,17 +22,32 @@ use relm::*;
 use relm::{interval, DrawHandler, Relm, Widget};
 use relm_derive::Msg;
 //use relm_derive::widget;
-use chrono::offset::{Local, TimeZone};
-use chrono::Date;
 use plotters::prelude::*;
 
+use updater::chart::{plot_span, ChartStyle, ChartView, Indicator, Period, PriceChart};
 use updater::ohlc::OHLC;
+use updater::store::{open_store_or_home, Store};
 
 use self::Msg::*;
 
+/// Factor of the bars shown per step of the mouse wheel or `+`/`-`.
+const ZOOM_STEP: f64 = 1.25;
+
 pub struct Model {
     draw_handler: DrawHandler<DrawingArea>,
+    store: Box<dyn Store>,
     cursor_pos: (f64, f64),
+    isin: Option<String>,
+    history: Vec<(NaiveDate, OHLC)>,
+    /// Time of the last fetch of the history when it was loaded.
+    fetched: Option<NaiveDateTime>,
+    view: ChartView,
+    /// Cursor position and view when the left button was pressed.
+    drag: Option<(f64, ChartView)>,
+    /// The chart has to be drawn again.
+    dirty: bool,
+    /// Size of the drawing area when the chart was drawn.
+    size: (i32, i32),
 }
 
 struct Win {
@@ -44,11 +59,129 @@ struct Win {
 
 #[derive(Msg)]
 pub enum Msg {
+    ButtonPress((f64, f64)),
+    ButtonRelease,
+    /// Reload the history if the updater changed it.
     Generate,
+    Key(u32),
+    /// Redraw if needed.
     Move,
     MoveCursor((f64, f64)),
     Quit,
+    /// Mouse wheel steps, positive to zoom out, at the x position.
+    Scroll(f64, f64),
     SelectIsin(String),
+    SelectPeriod(Period),
+}
+
+fn scroll_msg(event: &gdk::EventScroll) -> Option<Msg> {
+    let steps = match event.get_direction() {
+        ScrollDirection::Up => -1.0,
+        ScrollDirection::Down => 1.0,
+        ScrollDirection::Smooth => event.get_delta().1,
+        _ => return None,
+    };
+    Some(Scroll(steps, event.get_position().0))
+}
+
+/// The message of a key that pans or zooms the focused chart, which then goes no further.
+fn key_msg(event: &gdk::EventKey) -> (Option<Msg>, Inhibit) {
+    let keyval = event.get_keyval();
+    let handled = matches!(
+        keyval,
+        key::Left
+            | key::Right
+            | key::Page_Up
+            | key::Page_Down
+            | key::Home
+            | key::End
+            | key::plus
+            | key::KP_Add
+            | key::minus
+            | key::KP_Subtract
+    );
+    if handled {
+        (Some(Key(keyval)), Inhibit(true))
+    } else {
+        (None, Inhibit(false))
+    }
+}
+
+impl Win {
+    /// Left and right end of the plotted dates in the drawing area.
+    fn span(&self) -> (f64, f64) {
+        plot_span(self.drawing_area.get_allocated_width().max(0) as u32)
+    }
+
+    /// Position of `x` across the plotted dates, 0 at the left and 1 at the right end.
+    fn fraction(&self, x: f64) -> f64 {
+        let (left, right) = self.span();
+        ((x - left) / (right - left)).clamp(0.0, 1.0)
+    }
+
+    /// Load the history of the selected ISIN, unless it has not been fetched since.
+    fn load(&mut self) {
+        let isin = match self.model.isin {
+            Some(ref isin) => isin.clone(),
+            None => return,
+        };
+        let fetched = match self.model.store.fetch_logs(&isin) {
+            Ok(logs) => logs.iter().map(|log| log.fetched).max(),
+            Err(e) => {
+                println!("{}: {}", isin, e);
+                return;
+            }
+        };
+        if !self.model.history.is_empty() && fetched == self.model.fetched {
+            return;
+        }
+        match self.model.store.load(&isin) {
+            Ok(history) => {
+                if self.model.history.is_empty() {
+                    self.model.view = ChartView::period(&history, Period::Year);
+                } else {
+                    self.model.view.set_len(history.len());
+                }
+                self.model.history = history;
+                self.model.fetched = fetched;
+                self.model.dirty = true;
+            }
+            Err(e) => println!("{}: {}", isin, e),
+        }
+    }
+
+    fn draw(&mut self) {
+        let isin = match self.model.isin {
+            Some(ref isin) => isin.clone(),
+            None => return,
+        };
+        let chart = PriceChart {
+            title: isin,
+            style: ChartStyle::Candle,
+            log_scale: false,
+            overlays: vec![],
+            volume: true,
+            indicators: vec![Indicator::Rsi(14), Indicator::Macd(12, 26, 9)],
+        };
+        let size = (
+            self.drawing_area.get_allocated_width().max(1) as u32,
+            self.drawing_area.get_allocated_height().max(1) as u32,
+        );
+        let context = self.model.draw_handler.get_context();
+        let root = CairoBackend::new(&context, size)
+            .unwrap()
+            .into_drawing_area();
+        if let Err(e) = chart.draw(&root, &self.model.history, self.model.view.range()) {
+            println!("{}", e);
+        }
+    }
+
+    fn set_view(&mut self, view: ChartView) {
+        if view != self.model.view {
+            self.model.view = view;
+            self.model.dirty = true;
+        }
+    }
 }
 
 impl Update for Win {
@@ -57,64 +190,64 @@ impl Update for Win {
     type Msg = Msg;
 
     fn update(&mut self, event: Msg) {
+        let mut view = self.model.view;
+        let shown = (view.end - view.start) as isize;
         match event {
-            Generate => {},
-            Move => { }
-            MoveCursor(pos) => self.model.cursor_pos = pos,
+            ButtonPress(pos) => {
+                self.drawing_area.grab_focus();
+                self.model.drag = Some((pos.0, view));
+            }
+            ButtonRelease => self.model.drag = None,
+            Generate => self.load(),
+            Key(keyval) => {
+                match keyval {
+                    key::Left => view.pan(-(shown / 10).max(1)),
+                    key::Right => view.pan((shown / 10).max(1)),
+                    key::Page_Up => view.pan(-shown),
+                    key::Page_Down => view.pan(shown),
+                    key::Home => view.pan(-(view.len as isize)),
+                    key::End => view.pan(view.len as isize),
+                    key::plus | key::KP_Add => view.zoom(1.0 / ZOOM_STEP, 0.5),
+                    key::minus | key::KP_Subtract => view.zoom(ZOOM_STEP, 0.5),
+                    _ => (),
+                }
+                self.set_view(view);
+            }
+            Move => {
+                let size = (
+                    self.drawing_area.get_allocated_width(),
+                    self.drawing_area.get_allocated_height(),
+                );
+                if self.model.dirty || size != self.model.size {
+                    self.model.dirty = false;
+                    self.model.size = size;
+                    self.draw();
+                }
+            }
+            MoveCursor(pos) => {
+                self.model.cursor_pos = pos;
+                if let Some((x, mut view)) = self.model.drag {
+                    let shown = (view.end - view.start) as f64;
+                    let (left, right) = self.span();
+                    view.pan(((x - pos.0) * shown / (right - left)).round() as isize);
+                    self.set_view(view);
+                }
+            }
             Quit => gtk::main_quit(),
+            Scroll(steps, x) => {
+                view.zoom(ZOOM_STEP.powf(steps), self.fraction(x));
+                self.set_view(view);
+            }
             SelectIsin(isin) => {
                 println!("{}", isin);
-                let home_path = dirs::home_dir().unwrap();
-                let mut fname = home_path.clone();
-                fname.push("data");
-                fname.push("stock");
-                fname.push(isin.clone());
-                fname.push("ohlc.csv");
-                let f = File::open(fname).unwrap();
-                let part = OHLC::load_file(f).unwrap();
-                let mut part = part
-                    .iter()
-                    .map(|(d, e)| (chrono::Local.from_utc_date(&d) as Date<Local>, e))
-                    .collect::<Vec<_>>();
-
-                let context = self.model.draw_handler.get_context();
-                let root = CairoBackend::new(&context, (1024, 768))
-                    .unwrap()
-                    .into_drawing_area(); // 1000*1ms
-                root.fill(&WHITE).unwrap();
-
-                let day = chrono::NaiveDate::parse_from_str("2019-07-01", "%Y-%m-%d").unwrap();
-                let day = chrono::Local.from_utc_date(&day) as Date<Local>;
-                part.retain(|(d,_)| *d > day);
-
-                let from_date = part.first().unwrap().0;
-                let to_date = part.last().unwrap().0;
-                let from_y = part.iter().map(|e| e.1.low).fold(1. / 0., f32::min);
-                let to_y = part.iter().map(|e| e.1.high).fold(0. / 0., f32::max);
-                println!("{}", from_date);
-                let x_range = from_date..to_date;
-                let y_range = from_y..to_y;
-                //let y_range = plotters::coord::LogRange(y_range);
-                let mut chart = ChartBuilder::on(&root)
-                    .x_label_area_size(60)
-                    .y_label_area_size(60)
-                    .caption(isin, ("Arial", 24.0).into_font())
-                    .build_ranged(x_range, y_range)
-                    .unwrap();
-
-                chart
-                    .configure_mesh()
-                    .line_style_2(&WHITE)
-                    .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
-                    .draw()
-                    .unwrap();
-
-                chart
-                    .draw_series(part.into_iter().map(|(d, x)| {
-                        CandleStick::new(d, x.open, x.high, x.low, x.close, &GREEN, &RED, 1)
-                    }))
-                    .unwrap();
-                root.present().unwrap();
+                self.model.isin = Some(isin);
+                self.model.history.clear();
+                self.model.drag = None;
+                self.load();
+                self.drawing_area.grab_focus();
+            }
+            SelectPeriod(period) => {
+                self.set_view(ChartView::period(&self.model.history, period));
             }
         }
     }
@@ -122,7 +255,16 @@ impl Update for Win {
     fn model(_: &Relm<Self>, _: ()) -> Model {
         Model {
             draw_handler: DrawHandler::new().expect("draw handler"),
+            // The store is the only argument, by default ~/data/stock.
+            store: open_store_or_home(std::env::args().nth(1).as_deref()).expect("store"),
             cursor_pos: (-1000.0, -1000.0),
+            isin: None,
+            history: vec![],
+            fetched: None,
+            view: ChartView::period(&[], Period::Max),
+            drag: None,
+            dirty: false,
+            size: (0, 0),
         }
     }
 
@@ -141,7 +283,13 @@ impl Widget for Win {
 
     fn init_view(&mut self) {
         self.model.draw_handler.init(&self.drawing_area);
-        self.drawing_area.add_events(EventMask::POINTER_MOTION_MASK);
+        self.drawing_area.add_events(
+            EventMask::POINTER_MOTION_MASK
+                | EventMask::KEY_PRESS_MASK
+                | EventMask::BUTTON_PRESS_MASK
+                | EventMask::BUTTON_RELEASE_MASK
+                | EventMask::SCROLL_MASK,
+        );
     }
 
     //        gtk::Box {
@@ -170,8 +318,59 @@ impl Widget for Win {
             .height_request(400)
             .halign(gtk::Align::Fill)
             .hexpand(true)
+            .vexpand(true)
+            .can_focus(true)
             .build();
-        vbox.add(&drawing_area);
+        connect!(
+            relm,
+            drawing_area,
+            connect_motion_notify_event(_, event),
+            return (Some(MoveCursor(event.get_position())), Inhibit(false))
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_button_press_event(_, event),
+            return (
+                if event.get_button() == 1 {
+                    Some(ButtonPress(event.get_position()))
+                } else {
+                    None
+                },
+                Inhibit(false)
+            )
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_button_release_event(_, _),
+            return (Some(ButtonRelease), Inhibit(false))
+        );
+        connect!(
+            relm,
+            drawing_area,
+            connect_scroll_event(_, event),
+            return (scroll_msg(event), Inhibit(false))
+        );
+        // Only while the chart has the focus, so the list and buttons keep their keys.
+        connect!(
+            relm,
+            drawing_area,
+            connect_key_press_event(_, event),
+            return key_msg(event)
+        );
+
+        // Preset ranges above the chart.
+        let periods = gtk::Box::new(Horizontal, 0);
+        for period in Period::ALL.iter().cloned() {
+            let button = gtk::Button::new_with_label(&period.to_string());
+            connect!(relm, button, connect_clicked(_), SelectPeriod(period));
+            periods.add(&button);
+        }
+        let chart_box = gtk::Box::new(Vertical, 0);
+        chart_box.add(&periods);
+        chart_box.add(&drawing_area);
+        vbox.add(&chart_box);
 
         let isin_list = gtk::ListBoxBuilder::new()
             .width_request(100)
@@ -179,25 +378,15 @@ impl Widget for Win {
             .halign(gtk::Align::End)
             .build();
 
-        let home_path = dirs::home_dir().unwrap();
-        let mut path = home_path.clone();
-        path.push("data");
-        path.push("stock");
-        //let mut data = vec![];
-        for entry in path.read_dir().expect("read_dir call failed") {
-            if let Ok(entry) = entry {
-                let isin = entry.file_name().into_string().unwrap();
-                if entry.metadata().unwrap().is_dir() && isin.len() == 12 {
-                    let isin_label = gtk::Label::new(None);
-                    isin_label.set_markup(&format!("<small>{}</small>", isin));
-                    let isin_label = isin_label.upcast::<gtk::Widget>();
-                    let isin_entry = gtk::ListBoxRowBuilder::new()
-                        .name(&isin)
-                        .child(&isin_label)
-                        .build();
-                    isin_list.add(&isin_entry);
-                }
-            }
+        for isin in model.store.isins().expect("isins") {
+            let isin_label = gtk::Label::new(None);
+            isin_label.set_markup(&format!("<small>{}</small>", isin));
+            let isin_label = isin_label.upcast::<gtk::Widget>();
+            let isin_entry = gtk::ListBoxRowBuilder::new()
+                .name(&isin)
+                .child(&isin_label)
+                .build();
+            isin_list.add(&isin_entry);
         }
         let stream = relm.stream().clone();
         isin_list.connect_row_activated(move |_lb, entry| {
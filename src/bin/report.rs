@@ -0,0 +1,67 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::daily_report::{write_html, write_markdown, DailyReport, ReportSettings};
+use updater::error_def::*;
+use updater::market::read_isins;
+use updater::store::open_store_or_home;
+
+const USAGE: &str = "usage: report [--store DIR|DB] [--isins FILE] [--top N] [--lookback DAYS]
+              [--chart-days DAYS] [--output FILE.{html,md}] [ISIN ...]
+
+Writes the end-of-day report of the ISINs, given on the command line or in FILE,
+one per line, or else all of the store: last close and change, the N (default 5)
+top gainers and losers, new highs and lows and data quality warnings over DAYS
+(default 252) and a chart of the last closes (default 120) per instrument. The
+output is Markdown for .md files and HTML otherwise, by default report-DAY.html.";
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut isins = vec![];
+    let mut settings = ReportSettings::default();
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--isins" => isins.extend(read_isins(&next_arg(&mut args, USAGE)?)?),
+            "--top" => settings.top = next_arg(&mut args, USAGE)?.parse()?,
+            "--lookback" => settings.lookback = next_arg(&mut args, USAGE)?.parse()?,
+            "--chart-days" => settings.chart_days = next_arg(&mut args, USAGE)?.parse()?,
+            "--output" => output = Some(next_arg(&mut args, USAGE)?),
+            _ if arg.starts_with("--") => bail!(USAGE),
+            _ => isins.push(arg),
+        }
+    }
+    let store = open_store_or_home(store_path.as_deref())?;
+    if isins.is_empty() {
+        isins = store.isins()?;
+    }
+
+    let histories = isins
+        .iter()
+        .map(|isin| store.load(isin))
+        .collect::<Result<Vec<_>>>()?;
+    let report = DailyReport::build(&isins, &histories, &settings)?;
+    let output = output.unwrap_or_else(|| format!("report-{}.html", report.day));
+    let file = File::create(&output)?;
+    if output.ends_with(".md") || output.ends_with(".markdown") {
+        write_markdown(file, &report)?;
+    } else {
+        write_html(file, &report)?;
+    }
+    println!(
+        "report of {} instruments for {} written to {} ({} warnings)",
+        report.instruments.len(),
+        report.day,
+        output,
+        report.warnings.len()
+    );
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::risk::{
+    annualised_volatility, beta, correlation_matrix, drawdowns, historical_var, paired_returns,
+    parametric_var, returns, rolling_volatility, write_series,
+};
+use updater::store::open_store_or_home;
+
+const USAGE: &str = "usage: risk [--store DIR|DB] [--benchmark ISIN] [--window N] [--confidence C]
+            [--series FILE.csv] [ISIN...]
+
+Prints volatility, beta against the benchmark (default DE0008469008), drawdowns,
+historical and parametric value at risk of the daily returns of the ISINs, all of
+the store by default, and their correlation matrix. Beta and correlations use the
+days both instruments traded, the other figures the whole history of each.
+--series writes the rolling volatility over N returns (default 20) and drawdown per day.";
+
+const DEFAULT_BENCHMARK: &str = "DE0008469008";
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut benchmark = DEFAULT_BENCHMARK.to_string();
+    let mut window = 20;
+    let mut confidence = 0.95;
+    let mut series_path = None;
+    let mut isins = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--benchmark" => benchmark = next_arg(&mut args, USAGE)?,
+            "--window" => window = next_arg(&mut args, USAGE)?.parse()?,
+            "--confidence" => confidence = next_arg(&mut args, USAGE)?.parse()?,
+            "--series" => series_path = Some(next_arg(&mut args, USAGE)?),
+            _ if arg.starts_with("--") => bail!(USAGE),
+            _ => isins.push(arg),
+        }
+    }
+    if !(confidence > 0.0 && confidence < 1.0) {
+        bail!("Confidence must be between 0 and 1");
+    }
+    let store = open_store_or_home(store_path.as_deref())?;
+    if isins.is_empty() {
+        isins = store.isins()?;
+    }
+    if !isins.contains(&benchmark) {
+        isins.push(benchmark.clone());
+    }
+
+    let mut histories = vec![];
+    for isin in std::mem::take(&mut isins) {
+        let history = store.load(&isin)?;
+        if history.is_empty() {
+            log::warn!("No data for {}, skipped", isin);
+        } else {
+            isins.push(isin);
+            histories.push(history);
+        }
+    }
+    let b = match isins.iter().position(|isin| *isin == benchmark) {
+        Some(b) => b,
+        None => bail!("No data for the benchmark {}", benchmark),
+    };
+    println!("benchmark {}, VaR at {}", benchmark, confidence);
+    println!(
+        "{:<12} {:>10} {:>10} {:>6} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "isin",
+        "from",
+        "to",
+        "days",
+        "vol",
+        "vol now",
+        "beta",
+        "max dd",
+        "dd now",
+        "hVaR",
+        "hCVaR",
+        "pVaR",
+        "pCVaR"
+    );
+    let fmt = |v: Option<f64>| match v {
+        Some(v) => format!("{:>8.4}", v),
+        None => format!("{:>8}", "-"),
+    };
+    for (isin, history) in isins.iter().zip(histories.iter()) {
+        let returns = returns(history);
+        let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+        let dd = drawdowns(&closes);
+        let (paired, benchmark) = paired_returns(history, &histories[b]);
+        let historical = historical_var(&returns, confidence);
+        let parametric = parametric_var(&returns, confidence);
+        println!(
+            "{:<12} {:>10} {:>10} {:>6} {} {} {} {} {} {} {} {} {}",
+            isin,
+            history[0].0,
+            history[history.len() - 1].0,
+            history.len(),
+            fmt(annualised_volatility(&returns)),
+            fmt(rolling_volatility(&returns, window)
+                .last()
+                .cloned()
+                .unwrap_or(None)),
+            fmt(beta(&paired, &benchmark)),
+            fmt(Some(dd.iter().cloned().fold(0.0, f64::max))),
+            fmt(dd.last().cloned()),
+            fmt(historical.map(|v| v.var)),
+            fmt(historical.map(|v| v.expected_shortfall)),
+            fmt(parametric.map(|v| v.var)),
+            fmt(parametric.map(|v| v.expected_shortfall)),
+        );
+    }
+
+    println!();
+    print!("{:<12}", "");
+    for isin in isins.iter() {
+        print!(" {:>12}", isin);
+    }
+    println!();
+    for (isin, row) in isins.iter().zip(correlation_matrix(&histories).iter()) {
+        print!("{:<12}", isin);
+        for c in row.iter() {
+            print!(" {:>12.4}", c);
+        }
+        println!();
+    }
+
+    if let Some(path) = series_path {
+        write_series(File::create(&path)?, &isins, &histories, window)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
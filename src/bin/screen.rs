@@ -0,0 +1,91 @@
+use std::fs::File;
+
+use error_chain::bail;
+
+use updater::cli::{next_arg, run_main};
+use updater::error_def::*;
+use updater::screen::{screen, sort_rows, write_csv, Expr};
+use updater::store::open_store_or_home;
+
+const USAGE: &str = "usage: screen [--store DIR|DB] [--column EXPR]... [--sort EXPR] [--desc]
+              [--csv FILE.csv] [FILTER]
+
+Lists the instruments of the store for which the FILTER expression holds at their
+last day, e.g. 'close > sma(200) and rsi(14) < 30', with the close, the fields of
+the filter and the extra columns. Fields are open, high, low, close, sma(N), ema(N),
+rsi(N), high(N), low(N), change(N) and volatility(N); percentile(EXPR) ranks
+across the store.";
+
+fn run() -> Result<()> {
+    let mut store_path = None;
+    let mut extra = vec![];
+    let mut sort = None;
+    let mut descending = false;
+    let mut csv_path = None;
+    let mut filter = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--store" => store_path = Some(next_arg(&mut args, USAGE)?),
+            "--column" => extra.push(next_arg(&mut args, USAGE)?.parse::<Expr>()?),
+            "--sort" => sort = Some(next_arg(&mut args, USAGE)?.parse::<Expr>()?),
+            "--desc" => descending = true,
+            "--csv" => csv_path = Some(next_arg(&mut args, USAGE)?),
+            _ if arg.starts_with("--") || filter.is_some() => bail!(USAGE),
+            _ => filter = Some(arg.parse::<Expr>()?),
+        }
+    }
+    let store = open_store_or_home(store_path.as_deref())?;
+
+    let mut columns = vec![];
+    let candidates = filter
+        .iter()
+        .flat_map(|f| f.fields())
+        .map(Expr::Field)
+        .chain(extra)
+        .chain(sort.clone());
+    for c in std::iter::once("close".parse()?).chain(candidates) {
+        if !columns.contains(&c) {
+            columns.push(c);
+        }
+    }
+
+    let isins = store.isins()?;
+    let histories = isins
+        .iter()
+        .map(|isin| store.load(isin))
+        .collect::<Result<Vec<_>>>()?;
+    let mut rows = screen(&isins, &histories, filter.as_ref(), &columns);
+    if let Some(sort) = sort {
+        let column = columns.iter().position(|c| *c == sort).unwrap();
+        sort_rows(&mut rows, column, descending);
+    }
+
+    let headers = columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    let widths = headers.iter().map(|h| h.len().max(10)).collect::<Vec<_>>();
+    print!("{:<12} {:<10}", "isin", "day");
+    for (h, w) in headers.iter().zip(widths.iter()) {
+        print!(" {:>w$}", h, w = w);
+    }
+    println!();
+    for row in rows.iter() {
+        print!("{:<12} {:<10}", row.isin, row.day);
+        for (v, w) in row.values.iter().zip(widths.iter()) {
+            match v {
+                Some(v) => print!(" {:>w$.2}", v, w = w),
+                None => print!(" {:>w$}", "-", w = w),
+            }
+        }
+        println!();
+    }
+    println!("{} of {} instruments", rows.len(), isins.len());
+
+    if let Some(path) = csv_path {
+        write_csv(File::create(&path)?, &columns, &rows)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    run_main(run);
+}
@@ -1,21 +1,31 @@
+//use log::*;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
 
-//use log::*;
 use chrono::NaiveDate;
+use error_chain::bail;
 use scraper::{Html, Selector};
 
+use updater::alert::{evaluate, parse_notifier, read_rules, LogFile, Notifier};
+use updater::cli::next_arg;
 use updater::error_def::*;
+use updater::locale::NumberLocale;
+use updater::store::{open_store, FetchLog, Store};
 use updater::OHLC;
 
-fn update_isin(isin: String) -> Result<()> {
-    let fname = format!("stock/{}/ohlc.csv", isin);
-    let known_ohlc = match File::open(&fname) {
-        Ok(f) => OHLC::load_file(f)?,
-        _ => vec![],
-    };
+const USAGE: &str =
+    "usage: updater [--alerts RULES] [--alert-log FILE] [--notify NOTIFIER]... [STORE]
+
+Fetches the new bars of every ISIN of STORE (default stock/) and then checks the
+alert rules against the days added. NOTIFIER is log:FILE, command:SHELL COMMAND,
+mail:SPOOL FILE or webhook:URL of a local endpoint.";
+
+/// Headers of the column holding the traded units; others like Umsatz hold money.
+const VOLUME_HEADERS: &[&str] = &["stück", "stueck", "volumen", "volume"];
+
+/// Returns the last day stored before the update.
+fn update_isin(store: &dyn Store, isin: String) -> Result<Option<NaiveDate>> {
+    let known_ohlc = store.load(&isin)?;
+    let last_known = known_ohlc.last().map(|e| e.0);
 
     let range = match known_ohlc.last() {
         Some((ref d, _)) => {
@@ -37,77 +47,99 @@ fn update_isin(isin: String) -> Result<()> {
     println!("{}", url);
     let data = reqwest::get(&url)?.text()?;
 
-    let mut all_ohlc = known_ohlc.into_iter().collect::<HashMap<_, _>>();
+    let mut new_ohlc = vec![];
 
     let doc = data;
     let selector = Selector::parse("tr").unwrap();
+    let header_selector = Selector::parse("th").unwrap();
     let doc = Html::parse_document(&doc);
+    let volume_column = doc.select(&header_selector).position(|h| {
+        let name = h.text().collect::<String>().trim().to_lowercase();
+        VOLUME_HEADERS.contains(&name.as_str())
+    });
     for line in doc.select(&selector) {
         if line.value().classes().count() == 1 {
-            let mut fields = line.text();
+            let cells = line.text().collect::<Vec<_>>();
+            let mut fields = cells.iter().cloned();
             let day = NaiveDate::parse_from_str(fields.next().unwrap(), "%d.%m.%y")?;
-            let open: f32 = fields
-                .next()
-                .unwrap()
-                .replace(".", "")
-                .replace(',', ".")
-                .parse()?;
-            let low: f32 = fields
-                .next()
-                .unwrap()
-                .replace(".", "")
-                .replace(',', ".")
-                .parse()?;
-            let high: f32 = fields
-                .next()
-                .unwrap()
-                .replace(".", "")
-                .replace(',', ".")
-                .parse()?;
-            let close: f32 = fields
-                .next()
-                .unwrap()
-                .replace(".", "")
-                .replace(',', ".")
-                .parse()?;
+            let open = NumberLocale::German.parse(fields.next().unwrap())?;
+            let low = NumberLocale::German.parse(fields.next().unwrap())?;
+            let high = NumberLocale::German.parse(fields.next().unwrap())?;
+            let close = NumberLocale::German.parse(fields.next().unwrap())?;
+            let volume = volume_column
+                .and_then(|i| cells.get(i))
+                .and_then(|v| NumberLocale::German.parse(v).ok());
 
             let d_ohlc = OHLC {
                 open,
                 high,
                 low,
                 close,
+                volume,
             };
             println!("{} {}", day, d_ohlc);
-            all_ohlc.insert(day, d_ohlc);
+            new_ohlc.push((day, d_ohlc));
         }
     }
 
-    let mut all_ohlc = all_ohlc.into_iter().collect::<Vec<_>>();
-    all_ohlc.sort_by_key(|e| e.0);
-
-    if let Ok(mut f) = File::create(fname) {
-        for (day, e) in all_ohlc.into_iter() {
-            writeln!(
-                f,
-                "{} {:.5} {:.5} {:.5} {:.5}",
-                day, e.open, e.high, e.low, e.close
-            )?;
-        }
-    }
+    store.log_fetch(&FetchLog {
+        isin: isin.clone(),
+        fetched: chrono::Local::now().naive_local(),
+        url,
+        bars: new_ohlc.len(),
+    })?;
+    store.merge(&isin, new_ohlc)?;
 
-    Ok(())
+    Ok(last_known)
 }
 
 fn run() -> Result<()> {
     println!("Hello, world!");
 
-    let path = Path::new("stock/");
-    for entry in path.read_dir().expect("read_dir call failed") {
-        if let Ok(entry) = entry {
-            let isin = entry.file_name().into_string().unwrap();
-            if entry.metadata().unwrap().is_dir() && isin.len() == 12 {
-                println!("{:?}", isin);
-                update_isin(isin)?;
+    // The store defaults to the directory layout below `stock/`,
+    // pass e.g. `stock.db` to use an SQLite database instead.
+    let mut store_path = None;
+    let mut rules_path = None;
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--alerts" => rules_path = Some(next_arg(&mut args, USAGE)?),
+            "--alert-log" => notifiers.push(Box::new(LogFile(next_arg(&mut args, USAGE)?.into()))),
+            "--notify" => notifiers.push(parse_notifier(&next_arg(&mut args, USAGE)?)?),
+            _ if arg.starts_with("--") || store_path.is_some() => bail!(USAGE),
+            _ => store_path = Some(arg),
+        }
+    }
+    let store_path = store_path.unwrap_or_else(|| "stock/".to_string());
+    // Read before fetching to fail early on errors in the rules.
+    let rules = match rules_path {
+        Some(path) => read_rules(std::fs::File::open(&path)?)?,
+        None => vec![],
+    };
+
+    let store = open_store(&store_path)?;
+    let mut last_known = HashMap::new();
+    for isin in store.isins()?.into_iter() {
+        println!("{:?}", isin);
+        let last = update_isin(store.as_ref(), isin.clone())?;
+        last_known.insert(isin, last);
+    }
+
+    let mut alerts = vec![];
+    for rule in rules.iter() {
+        // Without stored days before the update only the last day is checked.
+        let since = last_known.get(&rule.isin).cloned().unwrap_or(None);
+        alerts.extend(evaluate(rule, &store.load(&rule.isin)?, since));
+    }
+    for alert in alerts.iter() {
+        println!("ALERT {}", alert);
+    }
+    if !alerts.is_empty() {
+        for notifier in notifiers.iter() {
+            // One failing notifier should not keep the alerts from the others.
+            if let Err(error) = notifier.notify(&alerts) {
+                println!("Notifier failed: {}", error);
             }
         }
     }
@@ -0,0 +1,844 @@
+//! Price charts of one instrument, with volume and oscillator panes below the
+//! prices, and charts of several instruments on one set of axes, written as PNG,
+//! SVG or animated GIF.
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{Date, Datelike, NaiveDate, TimeZone, Utc};
+use error_chain::bail;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::error_def::*;
+use crate::indicators;
+use crate::market::Market;
+use crate::ohlc::OHLC;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+    /// Animated, one frame per chunk of bars.
+    Gif,
+}
+
+impl ImageFormat {
+    /// By the extension of the file name.
+    pub fn from_path(path: &Path) -> Result<ImageFormat> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("svg") => Ok(ImageFormat::Svg),
+            Some("gif") => Ok(ImageFormat::Gif),
+            _ => bail!("Unknown image format of {:?}, use .png, .svg or .gif", path),
+        }
+    }
+}
+
+/// One line of a chart.
+#[derive(Clone, Debug)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(NaiveDate, f64)>,
+}
+
+/// Index of the first common day on or after `start`.
+fn start_index(market: &Market, start: Option<NaiveDate>) -> Result<usize> {
+    let t = match start {
+        Some(start) => market.days.iter().position(|d| d.0 >= start),
+        None => Some(0).filter(|_| !market.days.is_empty()),
+    };
+    match t {
+        Some(t) => Ok(t),
+        None => bail!("No common day from {:?} on", start),
+    }
+}
+
+/// The closes of every instrument scaled to 100 at the first common day on or after `start`.
+pub fn rebased(market: &Market, start: Option<NaiveDate>) -> Result<Vec<Series>> {
+    let t0 = start_index(market, start)?;
+    Ok(market
+        .isins
+        .iter()
+        .enumerate()
+        .map(|(i, isin)| {
+            let base = market.bar(i, t0).close;
+            Series {
+                name: isin.clone(),
+                points: (t0..market.days.len())
+                    .map(|t| (market.day(t), market.bar(i, t).close / base * 100.0))
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// The close of every other instrument divided by that of `benchmark`, scaled to
+/// 100 at the first common day on or after `start`. Rising lines beat the benchmark.
+pub fn relative_strength(
+    market: &Market,
+    benchmark: usize,
+    start: Option<NaiveDate>,
+) -> Result<Vec<Series>> {
+    let t0 = start_index(market, start)?;
+    let ratio = |i: usize, t: usize| market.bar(i, t).close / market.bar(benchmark, t).close;
+    Ok(market
+        .isins
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != benchmark)
+        .map(|(i, isin)| {
+            let base = ratio(i, t0);
+            Series {
+                name: format!("{} / {}", isin, market.isins[benchmark]),
+                points: (t0..market.days.len())
+                    .map(|t| (market.day(t), ratio(i, t) / base * 100.0))
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+pub(crate) fn plot_err<E: std::fmt::Display>(e: E) -> Error {
+    format!("Plotting failed: {}", e).into()
+}
+
+/// The x coordinate of a day.
+pub(crate) fn utc_date(day: NaiveDate) -> Date<Utc> {
+    Utc.from_utc_date(&day)
+}
+
+/// Draw the series as lines with a legend, the days on the x axis.
+pub fn draw_series<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    series: &[Series],
+) -> Result<()> {
+    let points = || series.iter().flat_map(|s| s.points.iter());
+    let (from, to) = match (points().map(|p| p.0).min(), points().map(|p| p.0).max()) {
+        (Some(from), Some(to)) if from < to => (from, to),
+        _ => bail!("Nothing to chart"),
+    };
+    let low = points().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let high = points().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let pad = (high - low) * 0.05;
+
+    root.fill(&WHITE).map_err(plot_err)?;
+    let mut chart = ChartBuilder::on(root)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .margin(10)
+        .caption(title, ("sans-serif", 24).into_font())
+        .build_ranged(utc_date(from)..utc_date(to), low - pad..high + pad)
+        .map_err(plot_err)?;
+    chart
+        .configure_mesh()
+        .line_style_2(&WHITE)
+        .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+        .draw()
+        .map_err(plot_err)?;
+    for (i, s) in series.iter().enumerate() {
+        let colour = Palette99::pick(i).to_rgba();
+        chart
+            .draw_series(LineSeries::new(
+                s.points.iter().map(|(d, v)| (utc_date(*d), *v)),
+                &colour,
+            ))
+            .map_err(plot_err)?
+            .label(s.name.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &colour));
+    }
+    chart
+        .configure_series_labels()
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()
+        .map_err(plot_err)?;
+    root.present().map_err(plot_err)?;
+    Ok(())
+}
+
+/// Line chart of the series written to `path`, PNG, SVG or a single GIF frame by its extension.
+pub fn plot_series(path: &Path, size: (u32, u32), title: &str, series: &[Series]) -> Result<()> {
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Png => draw_series(
+            &BitMapBackend::new(path, size).into_drawing_area(),
+            title,
+            series,
+        ),
+        ImageFormat::Svg => draw_series(
+            &SVGBackend::new(path, size).into_drawing_area(),
+            title,
+            series,
+        ),
+        ImageFormat::Gif => draw_series(
+            &BitMapBackend::gif(path, size, 0)
+                .map_err(plot_err)?
+                .into_drawing_area(),
+            title,
+            series,
+        ),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChartStyle {
+    Candle,
+    Line,
+    /// Open-high-low-close bars: the range with ticks left for the open and right for the close.
+    Bars,
+}
+
+impl FromStr for ChartStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ChartStyle> {
+        match s {
+            "candle" => Ok(ChartStyle::Candle),
+            "line" => Ok(ChartStyle::Line),
+            "ohlc" => Ok(ChartStyle::Bars),
+            _ => bail!("Unknown chart style: {}", s),
+        }
+    }
+}
+
+/// An indicator drawn over the prices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overlay {
+    Sma(usize),
+    Ema(usize),
+}
+
+impl Overlay {
+    pub fn values(&self, closes: &[f64]) -> Vec<Option<f64>> {
+        match *self {
+            Overlay::Sma(n) => indicators::sma(closes, n),
+            Overlay::Ema(n) => indicators::ema(closes, n),
+        }
+    }
+}
+
+impl FromStr for Overlay {
+    type Err = Error;
+
+    /// `sma:N` or `ema:N`.
+    fn from_str(s: &str) -> Result<Overlay> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next().map(|n| n.parse::<usize>())) {
+            (Some("sma"), Some(Ok(n))) if n > 0 => Ok(Overlay::Sma(n)),
+            (Some("ema"), Some(Ok(n))) if n > 0 => Ok(Overlay::Ema(n)),
+            _ => bail!("Invalid overlay: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overlay::Sma(n) => write!(f, "SMA {}", n),
+            Overlay::Ema(n) => write!(f, "EMA {}", n),
+        }
+    }
+}
+
+/// An oscillator drawn in a pane of its own below the prices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Indicator {
+    Rsi(usize),
+    /// Fast and slow average and signal line.
+    Macd(usize, usize, usize),
+}
+
+impl FromStr for Indicator {
+    type Err = Error;
+
+    /// `rsi:N`, `macd` for MACD 12 26 9 or `macd:FAST,SLOW,SIGNAL`.
+    fn from_str(s: &str) -> Result<Indicator> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap();
+        let args = match parts.next() {
+            Some(args) => args
+                .split(',')
+                .map(|n| n.parse::<usize>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok(),
+            None => Some(vec![]),
+        };
+        let indicator = match (kind, args.as_deref()) {
+            ("rsi", Some(&[n])) => Indicator::Rsi(n),
+            ("macd", Some(&[])) => Indicator::Macd(12, 26, 9),
+            ("macd", Some(&[fast, slow, signal])) if fast < slow => {
+                Indicator::Macd(fast, slow, signal)
+            }
+            _ => bail!("Invalid indicator: {}", s),
+        };
+        match indicator {
+            Indicator::Rsi(0) | Indicator::Macd(0, _, _) | Indicator::Macd(_, _, 0) => {
+                bail!("Invalid indicator: {}", s)
+            }
+            _ => Ok(indicator),
+        }
+    }
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Indicator::Rsi(n) => write!(f, "RSI {}", n),
+            Indicator::Macd(fast, slow, signal) => write!(f, "MACD {} {} {}", fast, slow, signal),
+        }
+    }
+}
+
+/// The prices of one instrument over time.
+#[derive(Clone, Debug)]
+pub struct PriceChart {
+    pub title: String,
+    pub style: ChartStyle,
+    pub log_scale: bool,
+    pub overlays: Vec<Overlay>,
+    /// A pane of volume bars, if the bars have a volume.
+    pub volume: bool,
+    /// One pane each, below the volume.
+    pub indicators: Vec<Indicator>,
+}
+
+type DateCoord = RangedCoord<RangedDate<Utc>, RangedCoordf64>;
+
+/// Margin around every pane of a price chart.
+const PANE_MARGIN: u32 = 10;
+/// Width of the price labels left of every pane.
+const PANE_Y_LABELS: u32 = 70;
+
+/// Left and right end in pixels of the dates plotted by a price chart `width` wide.
+pub fn plot_span(width: u32) -> (f64, f64) {
+    let left = (PANE_MARGIN + PANE_Y_LABELS) as f64;
+    let right = width.saturating_sub(PANE_MARGIN) as f64;
+    (left, right.max(left + 1.0))
+}
+
+/// A chart in one pane of a price chart. All panes have the same margins and
+/// y label area, so their x coordinates line up, and only the `bottom` one has
+/// the date labels.
+fn pane<'a, DB: DrawingBackend>(
+    area: &'a DrawingArea<DB, Shift>,
+    x_range: Range<Date<Utc>>,
+    y_range: Range<f64>,
+    bottom: bool,
+) -> Result<ChartContext<'a, DB, DateCoord>> {
+    ChartBuilder::on(area)
+        .x_label_area_size(if bottom { 40 } else { 0 })
+        .y_label_area_size(PANE_Y_LABELS)
+        .margin(PANE_MARGIN)
+        .build_ranged(x_range, y_range)
+        .map_err(plot_err)
+}
+
+/// Volumes in k, M or G.
+fn volume_label(v: f64) -> String {
+    match v.abs() {
+        a if a >= 1e9 => format!("{:.1}G", v / 1e9),
+        a if a >= 1e6 => format!("{:.1}M", v / 1e6),
+        a if a >= 1e3 => format!("{:.1}k", v / 1e3),
+        _ => format!("{:.0}", v),
+    }
+}
+
+fn draw_volume<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    x_range: Range<Date<Utc>>,
+    bars: &[(NaiveDate, OHLC)],
+    bar_width: u32,
+    bottom: bool,
+) -> Result<()> {
+    let high = bars
+        .iter()
+        .filter_map(|b| b.1.volume)
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let mut chart = pane(area, x_range, 0.0..high * 1.05, bottom)?;
+    chart
+        .configure_mesh()
+        .line_style_2(&WHITE)
+        .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+        .y_label_formatter(&|v| volume_label(*v))
+        .y_labels(5)
+        .y_desc("Volume")
+        .draw()
+        .map_err(plot_err)?;
+    chart
+        .draw_series(bars.iter().filter_map(|(d, b)| {
+            let colour = if b.close >= b.open { GREEN } else { RED };
+            b.volume.map(|v| {
+                PathElement::new(
+                    vec![(utc_date(*d), 0.0), (utc_date(*d), v)],
+                    colour.stroke_width(bar_width),
+                )
+            })
+        }))
+        .map_err(plot_err)?;
+    Ok(())
+}
+
+/// The indicator over the bars `range` of `history`, computed over the whole history.
+fn draw_indicator<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    x_range: Range<Date<Utc>>,
+    indicator: Indicator,
+    history: &[(NaiveDate, OHLC)],
+    range: Range<usize>,
+    bar_width: u32,
+    bottom: bool,
+) -> Result<()> {
+    let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+    let days = history[range.clone()]
+        .iter()
+        .map(|e| utc_date(e.0))
+        .collect::<Vec<_>>();
+    match indicator {
+        Indicator::Rsi(n) => {
+            let values = &indicators::rsi(&closes, n)[range];
+            let mut chart = pane(area, x_range, 0.0..100.0, bottom)?;
+            chart
+                .configure_mesh()
+                .line_style_2(&WHITE)
+                .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+                .y_labels(5)
+                .y_desc(indicator.to_string())
+                .draw()
+                .map_err(plot_err)?;
+            let (first, last) = (days[0], days[days.len() - 1]);
+            for level in [30.0, 70.0].iter() {
+                chart
+                    .draw_series(LineSeries::new(
+                        vec![(first, *level), (last, *level)],
+                        &RGBColor(160, 160, 160),
+                    ))
+                    .map_err(plot_err)?;
+            }
+            chart
+                .draw_series(LineSeries::new(
+                    days.iter()
+                        .zip(values.iter())
+                        .filter_map(|(d, v)| v.map(|v| (*d, v))),
+                    &BLUE,
+                ))
+                .map_err(plot_err)?;
+        }
+        Indicator::Macd(fast, slow, signal) => {
+            let values = &indicators::macd(&closes, fast, slow, signal)[range];
+            let mut low = 0.0f64;
+            let mut high = 0.0f64;
+            for (l, s, h) in values.iter().filter_map(|v| *v) {
+                low = low.min(l).min(s).min(h);
+                high = high.max(l).max(s).max(h);
+            }
+            let pad = ((high - low) * 0.05).max(1e-9);
+            let mut chart = pane(area, x_range, low - pad..high + pad, bottom)?;
+            chart
+                .configure_mesh()
+                .line_style_2(&WHITE)
+                .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+                .y_label_formatter(&|v| format!("{:.2}", v))
+                .y_labels(5)
+                .y_desc(indicator.to_string())
+                .draw()
+                .map_err(plot_err)?;
+            chart
+                .draw_series(days.iter().zip(values.iter()).filter_map(|(d, v)| {
+                    v.map(|(_, _, h)| {
+                        PathElement::new(
+                            vec![(*d, 0.0), (*d, h)],
+                            RGBColor(160, 160, 160).stroke_width(bar_width),
+                        )
+                    })
+                }))
+                .map_err(plot_err)?;
+            chart
+                .draw_series(LineSeries::new(
+                    days.iter()
+                        .zip(values.iter())
+                        .filter_map(|(d, v)| v.map(|v| (*d, v.0))),
+                    &BLUE,
+                ))
+                .map_err(plot_err)?;
+            chart
+                .draw_series(LineSeries::new(
+                    days.iter()
+                        .zip(values.iter())
+                        .filter_map(|(d, v)| v.map(|v| (*d, v.1))),
+                    &RED,
+                ))
+                .map_err(plot_err)?;
+        }
+    }
+    Ok(())
+}
+
+/// The bars and overlays on a chart whose y axis is the price, or its logarithm
+/// with `log_scale`.
+fn draw_prices<'a, DB: DrawingBackend + 'a>(
+    chart: &mut ChartContext<'a, DB, DateCoord>,
+    style: ChartStyle,
+    log_scale: bool,
+    bars: &[(NaiveDate, OHLC)],
+    overlays: &[(Overlay, &[Option<f64>])],
+    bar_width: u32,
+) -> Result<()> {
+    chart
+        .configure_mesh()
+        .line_style_2(&WHITE)
+        .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+        .y_label_formatter(&|v| format!("{:.2}", if log_scale { v.exp() } else { *v }))
+        .draw()
+        .map_err(plot_err)?;
+    let x = |d: &NaiveDate| utc_date(*d);
+    let y = |v: f64| if log_scale { v.ln() } else { v };
+    match style {
+        ChartStyle::Candle => {
+            chart
+                .draw_series(bars.iter().map(|(d, b)| {
+                    CandleStick::new(
+                        x(d),
+                        y(b.open),
+                        y(b.high),
+                        y(b.low),
+                        y(b.close),
+                        &GREEN,
+                        &RED,
+                        bar_width,
+                    )
+                }))
+                .map_err(plot_err)?;
+        }
+        ChartStyle::Line => {
+            chart
+                .draw_series(LineSeries::new(
+                    bars.iter().map(|(d, b)| (x(d), y(b.close))),
+                    &BLUE,
+                ))
+                .map_err(plot_err)?;
+        }
+        ChartStyle::Bars => {
+            let tick = (bar_width / 2).max(1) as i32;
+            let colour = |b: &OHLC| if b.close >= b.open { GREEN } else { RED };
+            chart
+                .draw_series(bars.iter().map(|(d, b)| {
+                    PathElement::new(vec![(x(d), y(b.low)), (x(d), y(b.high))], &colour(b))
+                }))
+                .map_err(plot_err)?;
+            chart
+                .draw_series(bars.iter().map(|(d, b)| {
+                    EmptyElement::at((x(d), y(b.open)))
+                        + PathElement::new(vec![(-tick, 0), (0, 0)], &colour(b))
+                }))
+                .map_err(plot_err)?;
+            chart
+                .draw_series(bars.iter().map(|(d, b)| {
+                    EmptyElement::at((x(d), y(b.close)))
+                        + PathElement::new(vec![(0, 0), (tick, 0)], &colour(b))
+                }))
+                .map_err(plot_err)?;
+        }
+    }
+    for (i, (overlay, values)) in overlays.iter().enumerate() {
+        let colour = Palette99::pick(i + 1).to_rgba();
+        chart
+            .draw_series(LineSeries::new(
+                bars.iter()
+                    .zip(values.iter())
+                    .filter_map(|((d, _), v)| v.map(|v| (x(d), y(v)))),
+                &colour,
+            ))
+            .map_err(plot_err)?
+            .label(overlay.to_string())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &colour));
+    }
+    if !overlays.is_empty() {
+        chart
+            .configure_series_labels()
+            .background_style(&WHITE.mix(0.8))
+            .border_style(&BLACK)
+            .draw()
+            .map_err(plot_err)?;
+    }
+    Ok(())
+}
+
+impl PriceChart {
+    /// Draw the bars `range` of `history`, with the volume and indicator panes below.
+    /// Overlays and indicators are computed over the whole history, so they start at
+    /// the first bar drawn if there is enough history before.
+    pub fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        history: &[(NaiveDate, OHLC)],
+        range: Range<usize>,
+    ) -> Result<()> {
+        let bars = &history[range.clone()];
+        if bars.len() < 2 {
+            bail!("Nothing to chart");
+        }
+        let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+        let values = self
+            .overlays
+            .iter()
+            .map(|o| o.values(&closes))
+            .collect::<Vec<_>>();
+        let overlays = self
+            .overlays
+            .iter()
+            .zip(values.iter())
+            .map(|(o, v)| (*o, &v[range.clone()]))
+            .collect::<Vec<_>>();
+
+        let mut low = f64::INFINITY;
+        let mut high = f64::NEG_INFINITY;
+        for (_, b) in bars.iter() {
+            let (l, h) = match self.style {
+                ChartStyle::Line => (b.close, b.close),
+                _ => (b.low, b.high),
+            };
+            low = low.min(l);
+            high = high.max(h);
+        }
+        for v in overlays.iter().flat_map(|o| o.1.iter()).filter_map(|v| *v) {
+            low = low.min(v);
+            high = high.max(v);
+        }
+        if self.log_scale && low <= 0.0 {
+            bail!("A log scale needs positive prices");
+        }
+        let x_range = utc_date(bars[0].0)..utc_date(bars[bars.len() - 1].0);
+        let (w, h) = root.dim_in_pixel();
+        let bar_width = ((w as f64 * 0.5 / bars.len() as f64) as u32).max(1);
+
+        // The panes below the prices take a fifth of the height each, together at
+        // most three fifths.
+        let volume = self.volume && bars.iter().any(|b| b.1.volume.is_some());
+        let panes = volume as u32 + self.indicators.len() as u32;
+        let pane_height = (h * 3 / 5).checked_div(panes).unwrap_or(0).min(h / 5);
+        root.fill(&WHITE).map_err(plot_err)?;
+        let (upper, lower) = root.split_vertically(h - pane_height * panes);
+
+        let mut builder = ChartBuilder::on(&upper);
+        builder
+            .x_label_area_size(if panes == 0 { 40 } else { 0 })
+            .y_label_area_size(PANE_Y_LABELS)
+            .margin(PANE_MARGIN)
+            .caption(&self.title, ("sans-serif", 24).into_font());
+        if self.log_scale {
+            low = low.ln();
+            high = high.ln();
+        }
+        let pad = ((high - low) * 0.05).max(high.abs() * 1e-6);
+        let mut chart = builder
+            .build_ranged(x_range.clone(), low - pad..high + pad)
+            .map_err(plot_err)?;
+        draw_prices(
+            &mut chart,
+            self.style,
+            self.log_scale,
+            bars,
+            &overlays,
+            bar_width,
+        )?;
+
+        if panes > 0 {
+            let areas = lower.split_evenly((panes as usize, 1));
+            let mut areas = areas.iter().enumerate();
+            let bottom = |i: usize| i + 1 == panes as usize;
+            if volume {
+                let (i, area) = areas.next().unwrap();
+                draw_volume(area, x_range.clone(), bars, bar_width, bottom(i))?;
+            }
+            for (indicator, (i, area)) in self.indicators.iter().zip(areas) {
+                draw_indicator(
+                    area,
+                    x_range.clone(),
+                    *indicator,
+                    history,
+                    range.clone(),
+                    bar_width,
+                    bottom(i),
+                )?;
+            }
+        }
+        root.present().map_err(plot_err)?;
+        Ok(())
+    }
+
+    /// Write the bars `range` of `history` to `path`, PNG, SVG or GIF by its extension.
+    /// A GIF shows `frame` bars per frame, one second each.
+    pub fn plot(
+        &self,
+        path: &Path,
+        size: (u32, u32),
+        history: &[(NaiveDate, OHLC)],
+        range: Range<usize>,
+        frame: usize,
+    ) -> Result<()> {
+        match ImageFormat::from_path(path)? {
+            ImageFormat::Png => self.draw(
+                &BitMapBackend::new(path, size).into_drawing_area(),
+                history,
+                range,
+            ),
+            ImageFormat::Svg => self.draw(
+                &SVGBackend::new(path, size).into_drawing_area(),
+                history,
+                range,
+            ),
+            ImageFormat::Gif => {
+                let root = BitMapBackend::gif(path, size, 1000)
+                    .map_err(plot_err)?
+                    .into_drawing_area();
+                let mut start = range.start;
+                while start + 1 < range.end {
+                    let end = (start + frame.max(2)).min(range.end);
+                    self.draw(&root, history, start..end)?;
+                    start = end;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Preset ranges of an interactive chart, back from the last bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Month,
+    Quarter,
+    Year,
+    FiveYears,
+    Max,
+}
+
+impl Period {
+    pub const ALL: [Period; 5] = [
+        Period::Month,
+        Period::Quarter,
+        Period::Year,
+        Period::FiveYears,
+        Period::Max,
+    ];
+
+    fn months(self) -> Option<i32> {
+        match self {
+            Period::Month => Some(1),
+            Period::Quarter => Some(3),
+            Period::Year => Some(12),
+            Period::FiveYears => Some(60),
+            Period::Max => None,
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Period> {
+        match s {
+            "1M" => Ok(Period::Month),
+            "3M" => Ok(Period::Quarter),
+            "1Y" => Ok(Period::Year),
+            "5Y" => Ok(Period::FiveYears),
+            "Max" => Ok(Period::Max),
+            _ => bail!("Unknown period: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Period::Month => "1M",
+            Period::Quarter => "3M",
+            Period::Year => "1Y",
+            Period::FiveYears => "5Y",
+            Period::Max => "Max",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The same day `months` months earlier, or the last day of that month.
+fn months_before(day: NaiveDate, months: i32) -> NaiveDate {
+    let m = day.year() * 12 + day.month0() as i32 - months;
+    let (year, month) = (m.div_euclid(12), m.rem_euclid(12) as u32 + 1);
+    (1..=day.day())
+        .rev()
+        .filter_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .next()
+        .unwrap()
+}
+
+/// Fewest bars an interactive chart zooms in to.
+const MIN_BARS: usize = 10;
+
+/// The bars `start..end` of a history of `len` bars shown by an interactive chart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChartView {
+    pub start: usize,
+    pub end: usize,
+    pub len: usize,
+}
+
+impl ChartView {
+    /// The bars of the period up to the last one.
+    pub fn period(history: &[(NaiveDate, OHLC)], period: Period) -> ChartView {
+        let len = history.len();
+        let start = match (period.months(), history.last()) {
+            (Some(months), Some((last, _))) => {
+                let from = months_before(*last, months);
+                history.iter().position(|e| e.0 > from).unwrap_or(len)
+            }
+            _ => 0,
+        };
+        ChartView {
+            start: start.min(len.saturating_sub(MIN_BARS.min(len))),
+            end: len,
+            len,
+        }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Show `factor` times as many bars, keeping the bar at `anchor` (0 left edge,
+    /// 1 right edge) in place.
+    pub fn zoom(&mut self, factor: f64, anchor: f64) {
+        let shown = self.end - self.start;
+        let anchor = anchor.clamp(0.0, 1.0);
+        let new = ((shown as f64 * factor).round() as usize)
+            .max(MIN_BARS.min(self.len))
+            .min(self.len);
+        let at = self.start as f64 + anchor * shown as f64;
+        let start = (at - anchor * new as f64).round().max(0.0) as usize;
+        self.start = start.min(self.len - new);
+        self.end = self.start + new;
+    }
+
+    /// Follow a history that is now `len` bars long, staying at its end if the
+    /// last bar was shown.
+    pub fn set_len(&mut self, len: usize) {
+        let shown = (self.end - self.start).min(len);
+        if self.end == self.len || self.end > len {
+            self.end = len;
+            self.start = len - shown;
+        }
+        self.len = len;
+    }
+
+    /// Move by `bars`, to later days if positive, staying within the history.
+    pub fn pan(&mut self, bars: isize) {
+        let shown = self.end - self.start;
+        let start = (self.start as isize + bars).max(0) as usize;
+        self.start = start.min(self.len - shown);
+        self.end = self.start + shown;
+    }
+}
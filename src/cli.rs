@@ -0,0 +1,23 @@
+//! What the command line tools share: option values and reporting errors.
+
+use error_chain::bail;
+
+use crate::error_def::*;
+
+/// The value of an option, or the usage as error if it is missing.
+pub fn next_arg(args: &mut impl Iterator<Item = String>, usage: &str) -> Result<String> {
+    match args.next() {
+        Some(arg) => Ok(arg),
+        None => bail!("{}", usage),
+    }
+}
+
+/// Run a tool with warnings logged, printing its error and exiting with 1 if it fails.
+pub fn run_main<F: FnOnce() -> Result<()>>(run: F) {
+    simple_logger::init_with_level(log::Level::Warn).unwrap();
+
+    if let Err(error) = run() {
+        println!("Error: {}", error);
+        std::process::exit(1);
+    }
+}
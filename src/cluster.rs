@@ -0,0 +1,466 @@
+//! Models grouping the input vectors into regimes, all feeding the same transition model.
+//!
+//! A cluster is identified by a `Node`: the grid position for the SOM, `(0, k)`
+//! for the k-th cluster of k-means and the Gaussian mixture. Pooling neighbouring
+//! nodes in the transition model therefore only makes sense for the SOM.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+use error_chain::bail;
+use ndarray::{Array1, Array2, Array3, ArrayView1};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::error_def::*;
+use crate::markov::Node;
+use crate::som::Som;
+
+/// A clustering model that can be trained, assigns rows to clusters and is saved
+/// as `key value` lines of a model file.
+pub trait Clusterer {
+    /// The name written as `method` into model files.
+    fn kind(&self) -> &'static str;
+
+    /// Train on the rows of `data`, `iterations` meaning what is usual for the method.
+    fn fit(&mut self, data: &Array2<f64>, iterations: u32, rng: &mut StdRng);
+
+    /// The cluster of one row.
+    fn assign(&self, v: ArrayView1<f64>) -> Node;
+
+    /// The clusters of all rows of `data`.
+    fn assign_all(&self, data: &Array2<f64>) -> Vec<Node> {
+        data.outer_iter().map(|v| self.assign(v)).collect()
+    }
+
+    /// Write the parameters, without the `method` line.
+    fn save(&self, w: &mut dyn Write) -> Result<()>;
+
+    /// The map, for the SOM specific plots.
+    fn as_som(&self) -> Option<&Som> {
+        None
+    }
+}
+
+/// Which clusterer to train: `som:ROWSxCOLS`, `kmeans:K` or `gmm:K`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Som(usize, usize),
+    KMeans(usize),
+    Gmm(usize),
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Method> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let size = parts.next().unwrap_or("");
+        let method = match name {
+            "som" => {
+                let mut dims = size.splitn(2, 'x');
+                match (dims.next(), dims.next()) {
+                    (Some(rows), Some(cols)) => Method::Som(rows.parse()?, cols.parse()?),
+                    _ => bail!("SOM size not given as ROWSxCOLS: {}", s),
+                }
+            }
+            "kmeans" => Method::KMeans(size.parse()?),
+            "gmm" => Method::Gmm(size.parse()?),
+            _ => bail!("Unknown clustering method: {}", s),
+        };
+        match method {
+            Method::Som(0, _) | Method::Som(_, 0) | Method::KMeans(0) | Method::Gmm(0) => {
+                bail!("No clusters in: {}", s)
+            }
+            _ => Ok(method),
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Som(rows, cols) => write!(f, "som:{}x{}", rows, cols),
+            Method::KMeans(k) => write!(f, "kmeans:{}", k),
+            Method::Gmm(k) => write!(f, "gmm:{}", k),
+        }
+    }
+}
+
+impl Method {
+    /// An untrained clusterer for rows of `inputs` values.
+    pub fn build(&self, inputs: usize, rng: &mut StdRng) -> Box<dyn Clusterer> {
+        match *self {
+            Method::Som(rows, cols) => Box::new(Som::new(rows, cols, inputs, rng)),
+            Method::KMeans(k) => Box::new(KMeans::new(k, inputs)),
+            Method::Gmm(k) => Box::new(Gmm::new(k, inputs)),
+        }
+    }
+
+    /// Training iterations if none are given: random samples for the SOM,
+    /// full passes over the data for the others.
+    pub fn default_iterations(&self) -> u32 {
+        match self {
+            Method::Som(_, _) => 2000,
+            Method::KMeans(_) | Method::Gmm(_) => 100,
+        }
+    }
+}
+
+fn join(values: impl Iterator<Item = f64>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_all<T: FromStr>(values: &HashMap<String, String>, key: &str) -> Result<Vec<T>> {
+    let mut parsed = vec![];
+    for v in values
+        .get(key)
+        .map(|s| s.as_str())
+        .unwrap_or("")
+        .split_whitespace()
+    {
+        match v.parse() {
+            Ok(v) => parsed.push(v),
+            _ => bail!("Not a number in model {}: {}", key, v),
+        }
+    }
+    Ok(parsed)
+}
+
+fn dim2(values: &HashMap<String, String>, key: &str) -> Result<(usize, usize)> {
+    match parse_all::<usize>(values, key)?.as_slice() {
+        &[k, inputs] => Ok((k, inputs)),
+        _ => bail!("Model without {}", key),
+    }
+}
+
+fn matrix(values: &HashMap<String, String>, key: &str, dim: (usize, usize)) -> Result<Array2<f64>> {
+    match Array2::from_shape_vec(dim, parse_all(values, key)?) {
+        Ok(m) => Ok(m),
+        _ => bail!("Model {} does not match its dimensions", key),
+    }
+}
+
+/// Restore a clusterer from the `key value` lines of a model file.
+/// Files without `method` line hold a SOM.
+pub fn load_clusterer(values: &HashMap<String, String>) -> Result<Box<dyn Clusterer>> {
+    match values.get("method").map(|s| s.as_str()).unwrap_or("som") {
+        "som" => {
+            let (rows, cols, inputs) = match parse_all::<usize>(values, "dim")?.as_slice() {
+                &[rows, cols, inputs] => (rows, cols, inputs),
+                _ => bail!("Model without dimensions"),
+            };
+            let weights = parse_all::<f64>(values, "weights")?;
+            let weights = match Array3::from_shape_vec((rows, cols, inputs), weights) {
+                Ok(w) => w,
+                _ => bail!("Model does not match its dimensions"),
+            };
+            let mut som = Som::from_weights(weights);
+            if let Some(lr) = values.get("learning_rate") {
+                som.learning_rate = lr.parse()?;
+            }
+            if let Some(sigma) = values.get("sigma") {
+                som.sigma = sigma.parse()?;
+            }
+            Ok(Box::new(som))
+        }
+        "kmeans" => {
+            let dim = dim2(values, "centroids_dim")?;
+            Ok(Box::new(KMeans {
+                centroids: matrix(values, "centroids", dim)?,
+            }))
+        }
+        "gmm" => {
+            let dim = dim2(values, "components_dim")?;
+            let weights = Array1::from(parse_all::<f64>(values, "mixture")?);
+            if weights.len() != dim.0 {
+                bail!("Model mixture does not match its dimensions");
+            }
+            Ok(Box::new(Gmm {
+                weights,
+                means: matrix(values, "means", dim)?,
+                variances: matrix(values, "variances", dim)?,
+            }))
+        }
+        method => bail!("Unknown clustering method in model: {}", method),
+    }
+}
+
+impl Clusterer for Som {
+    fn kind(&self) -> &'static str {
+        "som"
+    }
+
+    fn fit(&mut self, data: &Array2<f64>, iterations: u32, rng: &mut StdRng) {
+        self.train_random(data, iterations, rng);
+    }
+
+    fn assign(&self, v: ArrayView1<f64>) -> Node {
+        self.winner(v)
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<()> {
+        let (rows, cols, inputs) = self.dim();
+        writeln!(w, "dim {} {} {}", rows, cols, inputs)?;
+        writeln!(w, "learning_rate {}", self.learning_rate)?;
+        writeln!(w, "sigma {}", self.sigma)?;
+        writeln!(w, "weights {}", join(self.weights().iter().cloned()))?;
+        Ok(())
+    }
+
+    fn as_som(&self) -> Option<&Som> {
+        Some(self)
+    }
+}
+
+fn distance2(a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// K-means with k-means++ seeding.
+pub struct KMeans {
+    centroids: Array2<f64>,
+}
+
+impl KMeans {
+    pub fn new(k: usize, inputs: usize) -> KMeans {
+        KMeans {
+            centroids: Array2::zeros((k, inputs)),
+        }
+    }
+
+    pub fn centroids(&self) -> &Array2<f64> {
+        &self.centroids
+    }
+
+    fn nearest(&self, v: ArrayView1<f64>) -> usize {
+        let mut best = 0;
+        let mut best_d = 1. / 0.;
+        for (k, c) in self.centroids.outer_iter().enumerate() {
+            let d = distance2(c, v);
+            if d < best_d {
+                best_d = d;
+                best = k;
+            }
+        }
+        best
+    }
+
+    /// Pick the first centroid at random, every further one with probability
+    /// proportional to the squared distance to the closest centroid so far.
+    fn seed(&mut self, data: &Array2<f64>, rng: &mut StdRng) {
+        let n = data.rows();
+        let k = self.centroids.rows();
+        self.centroids
+            .row_mut(0)
+            .assign(&data.row(rng.gen_range(0, n)));
+        let mut d2 = data
+            .outer_iter()
+            .map(|v| distance2(v, self.centroids.row(0)))
+            .collect::<Vec<_>>();
+        for j in 1..k {
+            let total = d2.iter().sum::<f64>();
+            let mut pick = rng.gen::<f64>() * total;
+            let mut chosen = n - 1;
+            for (i, d) in d2.iter().enumerate() {
+                pick -= d;
+                if pick <= 0.0 {
+                    chosen = i;
+                    break;
+                }
+            }
+            if total == 0.0 {
+                chosen = rng.gen_range(0, n);
+            }
+            self.centroids.row_mut(j).assign(&data.row(chosen));
+            for (i, v) in data.outer_iter().enumerate() {
+                d2[i] = d2[i].min(distance2(v, self.centroids.row(j)));
+            }
+        }
+    }
+}
+
+impl Clusterer for KMeans {
+    fn kind(&self) -> &'static str {
+        "kmeans"
+    }
+
+    /// Lloyd's algorithm for at most `iterations` passes, stopping when no row
+    /// changes its cluster. Empty clusters restart at a random row.
+    fn fit(&mut self, data: &Array2<f64>, iterations: u32, rng: &mut StdRng) {
+        let (n, inputs) = data.dim();
+        let k = self.centroids.rows();
+        if n == 0 {
+            return;
+        }
+        self.seed(data, rng);
+        let mut assignment = vec![usize::MAX; n];
+        for _ in 0..iterations {
+            let mut changed = false;
+            for (i, v) in data.outer_iter().enumerate() {
+                let c = self.nearest(v);
+                if c != assignment[i] {
+                    assignment[i] = c;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+            let mut sums = Array2::<f64>::zeros((k, inputs));
+            let mut counts = vec![0; k];
+            for (v, c) in data.outer_iter().zip(assignment.iter()) {
+                let mut row = sums.row_mut(*c);
+                row += &v;
+                counts[*c] += 1;
+            }
+            for (j, count) in counts.iter().enumerate() {
+                if *count > 0 {
+                    let mean = sums.row(j).mapv(|s| s / *count as f64);
+                    self.centroids.row_mut(j).assign(&mean);
+                } else {
+                    self.centroids
+                        .row_mut(j)
+                        .assign(&data.row(rng.gen_range(0, n)));
+                }
+            }
+        }
+    }
+
+    fn assign(&self, v: ArrayView1<f64>) -> Node {
+        (0, self.nearest(v))
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<()> {
+        let (k, inputs) = self.centroids.dim();
+        writeln!(w, "centroids_dim {} {}", k, inputs)?;
+        writeln!(w, "centroids {}", join(self.centroids.iter().cloned()))?;
+        Ok(())
+    }
+}
+
+/// Smallest variance of a component, keeps components on few identical rows finite.
+const MIN_VARIANCE: f64 = 1e-6;
+
+/// Gaussian mixture with diagonal covariances, fitted by expectation maximisation
+/// starting from k-means.
+pub struct Gmm {
+    weights: Array1<f64>,
+    means: Array2<f64>,
+    variances: Array2<f64>,
+}
+
+impl Gmm {
+    pub fn new(k: usize, inputs: usize) -> Gmm {
+        Gmm {
+            weights: Array1::from_elem(k, 1.0 / k as f64),
+            means: Array2::zeros((k, inputs)),
+            variances: Array2::from_elem((k, inputs), 1.0),
+        }
+    }
+
+    /// Log of weight times density of every component at `v`.
+    fn log_joint(&self, v: ArrayView1<f64>) -> Vec<f64> {
+        let ln_2pi = (2.0 * std::f64::consts::PI).ln();
+        (0..self.weights.len())
+            .map(|j| {
+                let mut lp = self.weights[j].ln();
+                for ((x, m), var) in v
+                    .iter()
+                    .zip(self.means.row(j).iter())
+                    .zip(self.variances.row(j).iter())
+                {
+                    lp -= 0.5 * (ln_2pi + var.ln() + (x - m) * (x - m) / var);
+                }
+                lp
+            })
+            .collect()
+    }
+
+    /// Probability of every component given `v`.
+    pub fn responsibilities(&self, v: ArrayView1<f64>) -> Vec<f64> {
+        let lp = self.log_joint(v);
+        let max = lp.iter().cloned().fold(-1. / 0., f64::max);
+        let p = lp.iter().map(|l| (l - max).exp()).collect::<Vec<_>>();
+        let total = p.iter().sum::<f64>();
+        p.into_iter().map(|p| p / total).collect()
+    }
+}
+
+impl Clusterer for Gmm {
+    fn kind(&self) -> &'static str {
+        "gmm"
+    }
+
+    /// Start from k-means with the same number of clusters, then at most
+    /// `iterations` EM steps.
+    fn fit(&mut self, data: &Array2<f64>, iterations: u32, rng: &mut StdRng) {
+        let (n, inputs) = data.dim();
+        let k = self.weights.len();
+        if n == 0 {
+            return;
+        }
+        let mut kmeans = KMeans::new(k, inputs);
+        kmeans.fit(data, iterations, rng);
+        self.means = kmeans.centroids.clone();
+        let mut overall = Array1::<f64>::zeros(inputs);
+        let mean = data.mean_axis(ndarray::Axis(0));
+        for v in data.outer_iter() {
+            overall += &(&v - &mean).mapv(|d| d * d);
+        }
+        overall /= n as f64;
+        for mut row in self.variances.outer_iter_mut() {
+            row.assign(&overall.mapv(|v| v.max(MIN_VARIANCE)));
+        }
+
+        for _ in 0..iterations {
+            let resp = data
+                .outer_iter()
+                .map(|v| self.responsibilities(v))
+                .collect::<Vec<_>>();
+            for j in 0..k {
+                let nj = resp.iter().map(|r| r[j]).sum::<f64>();
+                if nj < 1e-10 {
+                    continue;
+                }
+                let mut mean = Array1::<f64>::zeros(inputs);
+                for (v, r) in data.outer_iter().zip(resp.iter()) {
+                    mean += &v.mapv(|x| x * r[j]);
+                }
+                mean /= nj;
+                let mut var = Array1::<f64>::zeros(inputs);
+                for (v, r) in data.outer_iter().zip(resp.iter()) {
+                    var += &(&v - &mean).mapv(|d| d * d * r[j]);
+                }
+                var /= nj;
+                self.weights[j] = nj / n as f64;
+                self.means.row_mut(j).assign(&mean);
+                self.variances
+                    .row_mut(j)
+                    .assign(&var.mapv(|v| v.max(MIN_VARIANCE)));
+            }
+        }
+    }
+
+    fn assign(&self, v: ArrayView1<f64>) -> Node {
+        let lp = self.log_joint(v);
+        let mut best = 0;
+        for (j, l) in lp.iter().enumerate() {
+            if *l > lp[best] {
+                best = j;
+            }
+        }
+        (0, best)
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<()> {
+        let (k, inputs) = self.means.dim();
+        writeln!(w, "components_dim {} {}", k, inputs)?;
+        writeln!(w, "mixture {}", join(self.weights.iter().cloned()))?;
+        writeln!(w, "means {}", join(self.means.iter().cloned()))?;
+        writeln!(w, "variances {}", join(self.variances.iter().cloned()))?;
+        Ok(())
+    }
+}
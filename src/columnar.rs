@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, Date32Array, Float64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+use error_chain::bail;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+use crate::store::{load_rows, save_rows, Row, Store};
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd(1970, 1, 1)
+}
+
+pub fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("isin", DataType::Utf8, false),
+        Field::new("date", DataType::Date32, false),
+        Field::new("open", DataType::Float64, false),
+        Field::new("high", DataType::Float64, false),
+        Field::new("low", DataType::Float64, false),
+        Field::new("close", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, true),
+    ])
+}
+
+fn to_batch(rows: &[Row]) -> Result<RecordBatch> {
+    let isin = StringArray::from(rows.iter().map(|r| r.0.as_str()).collect::<Vec<_>>());
+    let date = Date32Array::from(
+        rows.iter()
+            .map(|r| (r.1 - epoch()).num_days() as i32)
+            .collect::<Vec<_>>(),
+    );
+    let open = Float64Array::from(rows.iter().map(|r| r.2.open).collect::<Vec<_>>());
+    let high = Float64Array::from(rows.iter().map(|r| r.2.high).collect::<Vec<_>>());
+    let low = Float64Array::from(rows.iter().map(|r| r.2.low).collect::<Vec<_>>());
+    let close = Float64Array::from(rows.iter().map(|r| r.2.close).collect::<Vec<_>>());
+    let volume = Float64Array::from(rows.iter().map(|r| r.2.volume).collect::<Vec<_>>());
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(isin),
+        Arc::new(date),
+        Arc::new(open),
+        Arc::new(high),
+        Arc::new(low),
+        Arc::new(close),
+        Arc::new(volume),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema()), columns)?)
+}
+
+fn column_of<'a, T: 'static>(c: &'a ArrayRef, name: &str) -> Result<&'a T> {
+    match c.as_any().downcast_ref::<T>() {
+        Some(c) => Ok(c),
+        None => bail!("Mistyped column: {}", name),
+    }
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T> {
+    match batch.column_by_name(name) {
+        Some(c) => column_of(c, name),
+        None => bail!("Missing column: {}", name),
+    }
+}
+
+/// Price columns as `f64`, also accepting the `f32` columns of older exports.
+fn price_column(batch: &RecordBatch, name: &str) -> Result<Float64Array> {
+    let c = match batch.column_by_name(name) {
+        Some(c) => arrow::compute::cast(c, &DataType::Float64)?,
+        None => bail!("Missing column: {}", name),
+    };
+    Ok(column_of::<Float64Array>(&c, name)?.clone())
+}
+
+/// The volume column, which files written before it was stored lack.
+fn volume_column(batch: &RecordBatch) -> Result<Option<Float64Array>> {
+    match batch.column_by_name("volume") {
+        Some(_) => Ok(Some(price_column(batch, "volume")?)),
+        None => Ok(None),
+    }
+}
+
+fn from_batch(batch: &RecordBatch, rows: &mut Vec<Row>) -> Result<()> {
+    let isin = column::<StringArray>(batch, "isin")?;
+    let date = column::<Date32Array>(batch, "date")?;
+    let open = price_column(batch, "open")?;
+    let high = price_column(batch, "high")?;
+    let low = price_column(batch, "low")?;
+    let close = price_column(batch, "close")?;
+    let volume = volume_column(batch)?;
+    for i in 0..batch.num_rows() {
+        let day = epoch() + chrono::Duration::days(date.value(i) as i64);
+        let ohlc = OHLC {
+            open: open.value(i),
+            high: high.value(i),
+            low: low.value(i),
+            close: close.value(i),
+            volume: match volume {
+                Some(ref v) if v.is_valid(i) => Some(v.value(i)),
+                _ => None,
+            },
+        };
+        rows.push((isin.value(i).to_string(), day, ohlc));
+    }
+    Ok(())
+}
+
+pub fn write_parquet(path: &Path, rows: &[Row]) -> Result<()> {
+    let batch = to_batch(rows)?;
+    let f = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(f, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+pub fn read_parquet(path: &Path) -> Result<Vec<Row>> {
+    let f = File::open(path)?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(f)?.build()?;
+    let mut rows = vec![];
+    for batch in reader {
+        from_batch(&batch?, &mut rows)?;
+    }
+    Ok(rows)
+}
+
+/// Write an Arrow IPC file (also known as Feather v2).
+pub fn write_arrow(path: &Path, rows: &[Row]) -> Result<()> {
+    let batch = to_batch(rows)?;
+    let f = File::create(path)?;
+    let mut writer = arrow::ipc::writer::FileWriter::try_new(f, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn read_arrow(path: &Path) -> Result<Vec<Row>> {
+    let f = File::open(path)?;
+    let reader = arrow::ipc::reader::FileReader::try_new(f, None)?;
+    let mut rows = vec![];
+    for batch in reader {
+        from_batch(&batch?, &mut rows)?;
+    }
+    Ok(rows)
+}
+
+/// Choose the format from the file extension: `.parquet`, or `.arrow`/`.ipc`/`.feather`.
+pub fn is_parquet(path: &Path) -> Result<bool> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("parquet") => Ok(true),
+        Some("arrow") | Some("ipc") | Some("feather") => Ok(false),
+        _ => bail!("Unknown columnar format: {:?}", path),
+    }
+}
+
+pub fn write_file(path: &Path, rows: &[Row]) -> Result<()> {
+    if is_parquet(path)? {
+        write_parquet(path, rows)
+    } else {
+        write_arrow(path, rows)
+    }
+}
+
+pub fn read_file(path: &Path) -> Result<Vec<Row>> {
+    if is_parquet(path)? {
+        read_parquet(path)
+    } else {
+        read_arrow(path)
+    }
+}
+
+/// Export the given ISINs (all of the store if empty) into one columnar file.
+/// Returns the number of bars written.
+pub fn export(store: &dyn Store, isins: &[String], path: &Path) -> Result<usize> {
+    let rows = load_rows(store, isins)?;
+    write_file(path, &rows)?;
+    Ok(rows.len())
+}
+
+/// Read a columnar file back into the store, replacing the histories of the ISINs it contains.
+/// Returns the ISINs written.
+pub fn import(store: &dyn Store, path: &Path) -> Result<Vec<String>> {
+    save_rows(store, read_file(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parquet_and_arrow_round_trip() {
+        let rows = vec![
+            (
+                "DE0008469008".to_string(),
+                NaiveDate::from_ymd(2019, 10, 31),
+                OHLC {
+                    open: 12910.25,
+                    high: 12962.5,
+                    low: 12851.75,
+                    close: 12866.79,
+                    volume: Some(81_204_117.0),
+                },
+            ),
+            (
+                "US2605661048".to_string(),
+                NaiveDate::from_ymd(1969, 12, 31),
+                OHLC {
+                    open: 800.36,
+                    high: 807.29,
+                    low: 794.8,
+                    close: 800.36,
+                    volume: None,
+                },
+            ),
+        ];
+        for ext in &["parquet", "arrow"] {
+            let path =
+                std::env::temp_dir().join(format!("columnar-{}.{}", std::process::id(), ext));
+            write_file(&path, &rows).unwrap();
+            let read = read_file(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(read.unwrap(), rows);
+        }
+    }
+}
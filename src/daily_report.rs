@@ -0,0 +1,372 @@
+//! End-of-day report over the histories of instruments: last close and change of
+//! every instrument, top gainers and losers, new highs and lows, data quality
+//! warnings and a small chart per instrument, as one self-contained HTML or
+//! Markdown file.
+
+use std::io::Write;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+use plotters::prelude::*;
+
+use crate::chart::plot_err;
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+
+#[derive(Clone, Debug)]
+pub struct ReportSettings {
+    /// Number of gainers and losers listed.
+    pub top: usize,
+    /// Days back for new highs and lows.
+    pub lookback: usize,
+    /// Days shown in the chart of every instrument.
+    pub chart_days: usize,
+    /// Calendar days without a bar before an instrument counts as stale or gappy.
+    pub max_gap: i64,
+    /// Change of the close that is reported as suspicious, as a fraction.
+    pub max_jump: f64,
+}
+
+impl Default for ReportSettings {
+    fn default() -> ReportSettings {
+        ReportSettings {
+            top: 5,
+            lookback: 252,
+            chart_days: 120,
+            max_gap: 7,
+            max_jump: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InstrumentSummary {
+    pub isin: String,
+    pub day: NaiveDate,
+    pub close: f64,
+    /// Change against the previous close as a fraction, if there is one.
+    pub change: Option<f64>,
+    pub new_high: bool,
+    pub new_low: bool,
+    /// SVG chart of the last closes.
+    pub chart: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct DailyReport {
+    /// The latest day of all instruments.
+    pub day: NaiveDate,
+    pub instruments: Vec<InstrumentSummary>,
+    /// Indices into `instruments` of those with a bar on `day`, best and worst first.
+    pub gainers: Vec<usize>,
+    pub losers: Vec<usize>,
+    /// One message per problem found, prefixed by the ISIN.
+    pub warnings: Vec<String>,
+}
+
+/// Problems of the last `settings.lookback` bars of a history that hint at bad
+/// data, so older ones are not reported again every day.
+pub fn quality_warnings(
+    history: &[(NaiveDate, OHLC)],
+    day: NaiveDate,
+    settings: &ReportSettings,
+) -> Vec<String> {
+    let mut warnings = vec![];
+    if let Some((last, _)) = history.last() {
+        let behind = (day - *last).num_days();
+        if behind > settings.max_gap {
+            warnings.push(format!("stale, last bar {} days before {}", behind, day));
+        }
+    }
+    let recent = &history[history.len().saturating_sub(settings.lookback + 1)..];
+    for w in recent.windows(2) {
+        let ((d0, b0), (d1, b1)) = (&w[0], &w[1]);
+        let gap = (*d1 - *d0).num_days();
+        if gap > settings.max_gap {
+            warnings.push(format!("no bars for {} days before {}", gap, d1));
+        }
+        let jump = b1.close / b0.close - 1.0;
+        if jump.abs() > settings.max_jump {
+            warnings.push(format!("close changed {:+.1}% on {}", jump * 100.0, d1));
+        }
+    }
+    for (d, b) in recent[recent.len().saturating_sub(settings.lookback)..].iter() {
+        if b.open <= 0.0 || b.high <= 0.0 || b.low <= 0.0 || b.close <= 0.0 {
+            warnings.push(format!("non-positive price on {}", d));
+        } else if b.high < b.open.max(b.close) || b.low > b.open.min(b.close) {
+            warnings.push(format!("high/low outside open/close on {}", d));
+        }
+    }
+    warnings
+}
+
+/// Line of the closes without axes, green when the last close is above the first.
+pub fn chart_svg(closes: &[f64], size: (u32, u32)) -> Result<String> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        root.fill(&WHITE).map_err(plot_err)?;
+        if closes.len() > 1 {
+            let min = closes.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let pad = ((max - min) * 0.05).max(max.abs() * 1e-6);
+            let colour = if closes[closes.len() - 1] >= closes[0] {
+                RGBColor(0, 140, 0)
+            } else {
+                RGBColor(200, 0, 0)
+            };
+            let mut chart = ChartBuilder::on(&root)
+                .margin(2)
+                .build_ranged(0..closes.len() - 1, min - pad..max + pad)
+                .map_err(plot_err)?;
+            chart
+                .draw_series(LineSeries::new(closes.iter().cloned().enumerate(), &colour))
+                .map_err(plot_err)?;
+        }
+        root.present().map_err(plot_err)?;
+    }
+    Ok(svg)
+}
+
+impl DailyReport {
+    /// The report of the ISINs with their histories. Those without data are only
+    /// listed in the warnings.
+    pub fn build(
+        isins: &[String],
+        histories: &[Vec<(NaiveDate, OHLC)>],
+        settings: &ReportSettings,
+    ) -> Result<DailyReport> {
+        let day = match histories.iter().filter_map(|h| h.last()).map(|e| e.0).max() {
+            Some(day) => day,
+            None => bail!("No data for a report"),
+        };
+        let mut instruments = vec![];
+        let mut warnings = vec![];
+        for (isin, history) in isins.iter().zip(histories.iter()) {
+            let (last_day, last) = match history.last() {
+                Some(last) => last,
+                None => {
+                    warnings.push(format!("{}: no data", isin));
+                    continue;
+                }
+            };
+            let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+            let n = closes.len();
+            let recent = &closes[n.saturating_sub(settings.lookback)..];
+            let previous = &recent[..recent.len() - 1];
+            instruments.push(InstrumentSummary {
+                isin: isin.clone(),
+                day: *last_day,
+                close: last.close,
+                change: previous.last().map(|p| last.close / p - 1.0),
+                new_high: !previous.is_empty() && previous.iter().all(|c| last.close > *c),
+                new_low: !previous.is_empty() && previous.iter().all(|c| last.close < *c),
+                chart: chart_svg(&closes[n.saturating_sub(settings.chart_days)..], (240, 60))?,
+            });
+            for w in quality_warnings(history, day, settings) {
+                warnings.push(format!("{}: {}", isin, w));
+            }
+        }
+
+        let mut current = (0..instruments.len())
+            .filter(|i| instruments[*i].day == day && instruments[*i].change.is_some())
+            .collect::<Vec<_>>();
+        current.sort_by(|a, b| {
+            let (a, b) = (instruments[*a].change, instruments[*b].change);
+            b.unwrap().total_cmp(&a.unwrap())
+        });
+        let gainers = current
+            .iter()
+            .cloned()
+            .filter(|i| instruments[*i].change.unwrap() > 0.0)
+            .take(settings.top)
+            .collect();
+        let losers = current
+            .iter()
+            .rev()
+            .cloned()
+            .filter(|i| instruments[*i].change.unwrap() < 0.0)
+            .take(settings.top)
+            .collect();
+        Ok(DailyReport {
+            day,
+            instruments,
+            gainers,
+            losers,
+            warnings,
+        })
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn percent(change: Option<f64>) -> String {
+    match change {
+        Some(c) => format!("{:+.2}%", c * 100.0),
+        None => "-".to_string(),
+    }
+}
+
+fn marks(s: &InstrumentSummary) -> &'static str {
+    match (s.new_high, s.new_low) {
+        (true, _) => "new high",
+        (_, true) => "new low",
+        _ => "",
+    }
+}
+
+pub fn write_html<W: Write>(mut w: W, report: &DailyReport) -> Result<()> {
+    let list = |indices: &[usize]| {
+        indices
+            .iter()
+            .map(|i| {
+                let s = &report.instruments[*i];
+                format!(
+                    "<li>{} {} ({})</li>",
+                    escape_html(&s.isin),
+                    s.close,
+                    percent(s.change)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    writeln!(
+        w,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(w, "<title>Market report {}</title>", report.day)?;
+    writeln!(
+        w,
+        "<style>body {{ font-family: sans-serif; }} td, th {{ padding: 2px 8px; text-align: right; }} .up {{ color: green; }} .down {{ color: red; }}</style>"
+    )?;
+    writeln!(w, "</head>\n<body>\n<h1>Market report {}</h1>", report.day)?;
+    writeln!(
+        w,
+        "<h2>Top gainers</h2>\n<ol>\n{}\n</ol>",
+        list(&report.gainers)
+    )?;
+    writeln!(
+        w,
+        "<h2>Top losers</h2>\n<ol>\n{}\n</ol>",
+        list(&report.losers)
+    )?;
+
+    writeln!(w, "<h2>New highs and lows</h2>\n<ul>")?;
+    for s in report.instruments.iter().filter(|s| !marks(s).is_empty()) {
+        writeln!(w, "<li>{} {}</li>", escape_html(&s.isin), marks(s))?;
+    }
+    writeln!(w, "</ul>")?;
+
+    writeln!(w, "<h2>Instruments</h2>\n<table>")?;
+    writeln!(
+        w,
+        "<tr><th>ISIN</th><th>Day</th><th>Close</th><th>Change</th><th></th><th>Chart</th></tr>"
+    )?;
+    for s in report.instruments.iter() {
+        let class = match s.change {
+            Some(c) if c > 0.0 => "up",
+            Some(c) if c < 0.0 => "down",
+            _ => "",
+        };
+        writeln!(
+            w,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&s.isin),
+            s.day,
+            s.close,
+            class,
+            percent(s.change),
+            marks(s),
+            s.chart
+        )?;
+    }
+    writeln!(w, "</table>")?;
+
+    writeln!(w, "<h2>Data quality</h2>")?;
+    if report.warnings.is_empty() {
+        writeln!(w, "<p>No problems found.</p>")?;
+    } else {
+        writeln!(w, "<ul>")?;
+        for warning in report.warnings.iter() {
+            writeln!(w, "<li>{}</li>", escape_html(warning))?;
+        }
+        writeln!(w, "</ul>")?;
+    }
+    writeln!(w, "</body>\n</html>")?;
+    Ok(())
+}
+
+/// The chart as data URI, usable as Markdown image.
+fn svg_data_uri(svg: &str) -> String {
+    let mut uri = String::from("data:image/svg+xml,");
+    for b in svg.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~/=:;,".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+pub fn write_markdown<W: Write>(mut w: W, report: &DailyReport) -> Result<()> {
+    writeln!(w, "# Market report {}\n", report.day)?;
+    for (title, indices) in [
+        ("Top gainers", &report.gainers),
+        ("Top losers", &report.losers),
+    ]
+    .iter()
+    {
+        writeln!(w, "## {}\n", title)?;
+        for (rank, i) in indices.iter().enumerate() {
+            let s = &report.instruments[*i];
+            writeln!(
+                w,
+                "{}. {} {} ({})",
+                rank + 1,
+                s.isin,
+                s.close,
+                percent(s.change)
+            )?;
+        }
+        writeln!(w)?;
+    }
+
+    writeln!(w, "## New highs and lows\n")?;
+    for s in report.instruments.iter().filter(|s| !marks(s).is_empty()) {
+        writeln!(w, "- {} {}", s.isin, marks(s))?;
+    }
+    writeln!(w)?;
+
+    writeln!(w, "## Instruments\n")?;
+    writeln!(w, "| ISIN | Day | Close | Change | | Chart |")?;
+    writeln!(w, "|---|---|--:|--:|---|---|")?;
+    for s in report.instruments.iter() {
+        writeln!(
+            w,
+            "| {} | {} | {} | {} | {} | ![{}]({}) |",
+            s.isin,
+            s.day,
+            s.close,
+            percent(s.change),
+            marks(s),
+            s.isin,
+            svg_data_uri(&s.chart)
+        )?;
+    }
+
+    writeln!(w, "\n## Data quality\n")?;
+    if report.warnings.is_empty() {
+        writeln!(w, "No problems found.")?;
+    }
+    for warning in report.warnings.iter() {
+        writeln!(w, "- {}", warning)?;
+    }
+    Ok(())
+}
@@ -3,9 +3,15 @@ use error_chain::*;
 error_chain! {
     foreign_links {
         ParseFloat(std::num::ParseFloatError);
+        ParseInt(std::num::ParseIntError);
         ParseDate(chrono::format::ParseError);
         Io(std::io::Error);
+        Csv(csv::Error);
         Reqwest(reqwest::Error);
+        Arrow(arrow::error::ArrowError);
+        Parquet(parquet::errors::ParquetError);
+        Sqlite(rusqlite::Error);
+        Json(serde_json::Error) #[cfg(feature = "serialize")];
         //ImageErr(image::ImageError);
     }
 
@@ -0,0 +1,498 @@
+//! Composable feature extraction for the input vectors of the analysis.
+//!
+//! A pipeline is written as a list of features separated by `;` or newlines,
+//! each optionally followed by its normalisation:
+//!
+//! ```text
+//! weekday
+//! bar(*) scale(20)
+//! return(DE0008469008, 5) zscore
+//! spread(0, 1, 1) zscore
+//! rsi(0, 14) minmax
+//! ```
+//!
+//! Instruments are given by ISIN or by position in the analysed list,
+//! `*` repeats the feature for every instrument.
+
+use std::f64::consts::PI;
+
+use chrono::Datelike;
+use error_chain::bail;
+use ndarray::Array2;
+
+use crate::error_def::*;
+use crate::indicators;
+use crate::market::Market;
+
+/// The default pipeline, the input vector `boerse` has always used.
+pub const DEFAULT_PIPELINE: &str = "weekday; bar(*) scale(20)";
+
+/// A group of input values computed for one common day of a market.
+pub trait Feature {
+    /// One name per value, e.g. `DE0008469008.close`.
+    fn names(&self, market: &Market) -> Vec<String>;
+
+    /// The values on common day `t`, using only data up to that day.
+    /// `None` if the history is too short.
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>>;
+}
+
+fn closes(market: &Market, i: usize, t: usize) -> Vec<f64> {
+    market.history(i, t).iter().map(|e| e.1.close).collect()
+}
+
+fn last_close(market: &Market, i: usize, t: usize) -> Option<f64> {
+    let h = market.history(i, t);
+    if h.len() < 2 {
+        None
+    } else {
+        Some(h[h.len() - 2].1.close)
+    }
+}
+
+fn n_day_return(market: &Market, i: usize, t: usize, n: usize) -> Option<f64> {
+    let h = market.history(i, t);
+    if n == 0 || h.len() <= n {
+        None
+    } else {
+        Some(h[h.len() - 1].1.close / h[h.len() - 1 - n].1.close - 1.0)
+    }
+}
+
+/// One-hot weekday, Monday to Friday.
+pub struct Weekday;
+
+impl Feature for Weekday {
+    fn names(&self, _market: &Market) -> Vec<String> {
+        ["mon", "tue", "wed", "thu", "fri"]
+            .iter()
+            .map(|d| format!("weekday.{}", d))
+            .collect()
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        Some(crate::ohlcx::weekday_f64_vec(&market.day(t)))
+    }
+}
+
+/// Position in the year as a point on the unit circle.
+pub struct DayOfYear;
+
+impl Feature for DayOfYear {
+    fn names(&self, _market: &Market) -> Vec<String> {
+        vec!["dayofyear.sin".to_string(), "dayofyear.cos".to_string()]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let a = 2.0 * PI * market.day(t).ordinal() as f64 / 365.25;
+        Some(vec![a.sin(), a.cos()])
+    }
+}
+
+/// Open, high, low and close relative to the last close, minus one.
+pub struct Bar(pub usize);
+
+impl Feature for Bar {
+    fn names(&self, market: &Market) -> Vec<String> {
+        ["open", "high", "low", "close"]
+            .iter()
+            .map(|c| format!("{}.{}", market.isins[self.0], c))
+            .collect()
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let lc = last_close(market, self.0, t)?;
+        let e = market.bar(self.0, t);
+        Some(vec![
+            e.open / lc - 1.0,
+            e.high / lc - 1.0,
+            e.low / lc - 1.0,
+            e.close / lc - 1.0,
+        ])
+    }
+}
+
+/// Return of the close over the last `n` bars.
+pub struct Return(pub usize, pub usize);
+
+impl Feature for Return {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.return{}", market.isins[self.0], self.1)]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        Some(vec![n_day_return(market, self.0, t, self.1)?])
+    }
+}
+
+/// High minus low relative to the last close.
+pub struct Range(pub usize);
+
+impl Feature for Range {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.range", market.isins[self.0])]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let lc = last_close(market, self.0, t)?;
+        let e = market.bar(self.0, t);
+        Some(vec![(e.high - e.low) / lc])
+    }
+}
+
+/// Open relative to the last close, minus one.
+pub struct Gap(pub usize);
+
+impl Feature for Gap {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.gap", market.isins[self.0])]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let lc = last_close(market, self.0, t)?;
+        Some(vec![market.bar(self.0, t).open / lc - 1.0])
+    }
+}
+
+/// Close relative to its `n`-bar simple moving average, minus one.
+pub struct Sma(pub usize, pub usize);
+
+impl Feature for Sma {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.sma{}", market.isins[self.0], self.1)]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let c = closes(market, self.0, t);
+        let sma = (*indicators::sma(&c, self.1).last()?)?;
+        Some(vec![c[c.len() - 1] / sma - 1.0])
+    }
+}
+
+/// Relative strength index over `n` bars.
+pub struct Rsi(pub usize, pub usize);
+
+impl Feature for Rsi {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.rsi{}", market.isins[self.0], self.1)]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let c = closes(market, self.0, t);
+        Some(vec![(*indicators::rsi(&c, self.1).last()?)?])
+    }
+}
+
+/// MACD(12, 26, 9) histogram relative to the close.
+pub struct Macd(pub usize);
+
+impl Feature for Macd {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!("{}.macd", market.isins[self.0])]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let c = closes(market, self.0, t);
+        let (_, _, hist) = (*indicators::macd(&c, 12, 26, 9).last()?)?;
+        Some(vec![hist / c[c.len() - 1]])
+    }
+}
+
+/// Return of one instrument minus the return of another over `n` bars.
+pub struct Spread(pub usize, pub usize, pub usize);
+
+impl Feature for Spread {
+    fn names(&self, market: &Market) -> Vec<String> {
+        vec![format!(
+            "{}-{}.spread{}",
+            market.isins[self.0], market.isins[self.1], self.2
+        )]
+    }
+
+    fn extract(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let a = n_day_return(market, self.0, t, self.2)?;
+        let b = n_day_return(market, self.1, t, self.2)?;
+        Some(vec![a - b])
+    }
+}
+
+/// How the values of a feature are brought to comparable size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalisation {
+    None,
+    /// Multiply by a constant.
+    Scale(f64),
+    /// Subtract the mean and divide by the standard deviation of the training data.
+    ZScore,
+    /// Map the range of the training data to 0..1.
+    MinMax,
+}
+
+struct Entry {
+    feature: Box<dyn Feature>,
+    norm: Normalisation,
+    width: usize,
+}
+
+/// Features with their normalisation, producing the rows of the analysis.
+pub struct Pipeline {
+    /// The textual definition the pipeline was parsed from.
+    pub spec: String,
+    entries: Vec<Entry>,
+    names: Vec<String>,
+    /// Per column `(offset, factor)`: normalised = (raw - offset) * factor.
+    params: Vec<(f64, f64)>,
+}
+
+/// Split `name(a, b) rest` into name, arguments and the rest.
+fn parse_call(s: &str) -> Result<(String, Vec<String>, &str)> {
+    let s = s.trim_start();
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    let name = s[..end].to_lowercase();
+    let rest = s[end..].trim_start();
+    if rest.starts_with('(') {
+        match rest.find(')') {
+            Some(close) => {
+                let args = rest[1..close]
+                    .split(',')
+                    .map(|a| a.trim().to_string())
+                    .filter(|a| !a.is_empty())
+                    .collect();
+                Ok((name, args, &rest[close + 1..]))
+            }
+            None => bail!("Missing ')' in feature: {}", s),
+        }
+    } else {
+        Ok((name, vec![], rest))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T> {
+    match s.parse() {
+        Ok(n) => Ok(n),
+        _ => bail!("Not a number: {}", s),
+    }
+}
+
+/// The instruments an argument refers to: all for `*`, else one.
+fn instruments(market: &Market, s: &str) -> Result<Vec<usize>> {
+    if s == "*" {
+        Ok((0..market.isins.len()).collect())
+    } else {
+        Ok(vec![market.instrument(s)?])
+    }
+}
+
+fn parse_features(market: &Market, name: &str, args: &[String]) -> Result<Vec<Box<dyn Feature>>> {
+    let arg = |k: usize| -> Result<&str> {
+        match args.get(k) {
+            Some(a) => Ok(a.as_str()),
+            None => bail!("Feature {} needs {} arguments", name, k + 1),
+        }
+    };
+    let mut features: Vec<Box<dyn Feature>> = vec![];
+    match name {
+        "weekday" => features.push(Box::new(Weekday)),
+        "dayofyear" => features.push(Box::new(DayOfYear)),
+        "bar" => {
+            for i in instruments(market, arg(0)?)? {
+                features.push(Box::new(Bar(i)));
+            }
+        }
+        "range" => {
+            for i in instruments(market, arg(0)?)? {
+                features.push(Box::new(Range(i)));
+            }
+        }
+        "gap" => {
+            for i in instruments(market, arg(0)?)? {
+                features.push(Box::new(Gap(i)));
+            }
+        }
+        "macd" => {
+            for i in instruments(market, arg(0)?)? {
+                features.push(Box::new(Macd(i)));
+            }
+        }
+        "return" | "sma" | "rsi" => {
+            let n = parse_number(arg(1)?)?;
+            for i in instruments(market, arg(0)?)? {
+                features.push(match name {
+                    "return" => Box::new(Return(i, n)),
+                    "sma" => Box::new(Sma(i, n)),
+                    _ => Box::new(Rsi(i, n)),
+                });
+            }
+        }
+        "spread" => {
+            let a = market.instrument(arg(0)?)?;
+            let b = market.instrument(arg(1)?)?;
+            features.push(Box::new(Spread(a, b, parse_number(arg(2)?)?)));
+        }
+        _ => bail!("Unknown feature: {}", name),
+    }
+    Ok(features)
+}
+
+fn parse_normalisation(name: &str, args: &[String]) -> Result<Normalisation> {
+    match (name, args.len()) {
+        ("", 0) | ("none", 0) => Ok(Normalisation::None),
+        ("scale", 1) => match parse_number::<f64>(&args[0])? {
+            k if k.is_finite() && k != 0.0 => Ok(Normalisation::Scale(k)),
+            _ => bail!("Scale needs a finite factor other than 0: {}", args[0]),
+        },
+        ("zscore", 0) => Ok(Normalisation::ZScore),
+        ("minmax", 0) => Ok(Normalisation::MinMax),
+        _ => bail!("Unknown normalisation: {}", name),
+    }
+}
+
+impl Pipeline {
+    /// Parse a pipeline for the instruments of `market`.
+    pub fn parse(spec: &str, market: &Market) -> Result<Pipeline> {
+        let mut entries = vec![];
+        for item in spec.split([';', '\n']) {
+            let item = item.trim();
+            if item.is_empty() || item.starts_with('#') {
+                continue;
+            }
+            let (name, args, rest) = parse_call(item)?;
+            let (norm_name, norm_args, rest) = parse_call(rest)?;
+            if !rest.trim().is_empty() {
+                bail!("Unexpected text in feature: {}", item);
+            }
+            let norm = parse_normalisation(&norm_name, &norm_args)?;
+            for feature in parse_features(market, &name, &args)? {
+                let width = feature.names(market).len();
+                entries.push(Entry {
+                    feature,
+                    norm,
+                    width,
+                });
+            }
+        }
+        if entries.is_empty() {
+            bail!("Empty feature pipeline");
+        }
+        let names = entries
+            .iter()
+            .flat_map(|e| e.feature.names(market))
+            .collect::<Vec<_>>();
+        let params = vec![(0.0, 1.0); names.len()];
+        Ok(Pipeline {
+            spec: spec.to_string(),
+            entries,
+            names,
+            params,
+        })
+    }
+
+    /// The names of all columns.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn width(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The unnormalised row of common day `t`, `None` if any feature lacks history.
+    pub fn raw(&self, market: &Market, t: usize) -> Option<Vec<f64>> {
+        let mut row = Vec::with_capacity(self.width());
+        for e in self.entries.iter() {
+            row.extend(e.feature.extract(market, t)?);
+        }
+        Some(row)
+    }
+
+    /// Determine the normalisation parameters from training rows.
+    pub fn fit(&mut self, rows: &[Vec<f64>]) {
+        let mut col = 0;
+        for e in self.entries.iter() {
+            for c in col..col + e.width {
+                let values = rows.iter().map(|r| r[c]);
+                self.params[c] = match e.norm {
+                    Normalisation::None => (0.0, 1.0),
+                    Normalisation::Scale(k) => (0.0, k),
+                    Normalisation::ZScore => {
+                        let n = rows.len().max(1) as f64;
+                        let mean = values.clone().sum::<f64>() / n;
+                        let var = values.map(|v| (v - mean) * (v - mean)).sum::<f64>() / n;
+                        let sd = var.sqrt();
+                        (mean, if sd > 0.0 { 1.0 / sd } else { 1.0 })
+                    }
+                    Normalisation::MinMax => {
+                        let min = values.clone().fold(1. / 0., f64::min);
+                        let max = values.fold(-1. / 0., f64::max);
+                        if max > min {
+                            (min, 1.0 / (max - min))
+                        } else {
+                            (0.0, 1.0)
+                        }
+                    }
+                };
+            }
+            col += e.width;
+        }
+    }
+
+    /// Per column `(offset, factor)` with normalised = (raw - offset) * factor.
+    pub fn params(&self) -> &[(f64, f64)] {
+        &self.params
+    }
+
+    /// Restore normalisation parameters determined earlier by `fit`.
+    pub fn set_params(&mut self, params: Vec<(f64, f64)>) -> Result<()> {
+        if params.len() != self.width() {
+            bail!(
+                "Expected {} normalisation parameters, got {}",
+                self.width(),
+                params.len()
+            );
+        }
+        self.params = params;
+        Ok(())
+    }
+
+    pub fn normalise(&self, raw: &[f64]) -> Vec<f64> {
+        raw.iter()
+            .zip(self.params.iter())
+            .map(|(v, (offset, factor))| (v - offset) * factor)
+            .collect()
+    }
+
+    /// Map normalised values back to raw feature values.
+    pub fn denormalise(&self, values: &[f64]) -> Vec<f64> {
+        values
+            .iter()
+            .zip(self.params.iter())
+            .map(|(v, (offset, factor))| v / factor + offset)
+            .collect()
+    }
+
+    /// The raw rows of all common days with complete history, as `(t, row)`.
+    pub fn raw_rows(&self, market: &Market) -> Vec<(usize, Vec<f64>)> {
+        (0..market.days.len())
+            .filter_map(|t| self.raw(market, t).map(|row| (t, row)))
+            .collect()
+    }
+
+    /// Fit the normalisation to all days of the market and return the normalised rows
+    /// together with the common day index of each row.
+    pub fn fit_transform(&mut self, market: &Market) -> (Vec<usize>, Array2<f64>) {
+        let (days, rows): (Vec<_>, Vec<_>) = self.raw_rows(market).into_iter().unzip();
+        self.fit(&rows);
+        (days, self.transform(&rows))
+    }
+
+    /// Normalise rows into a matrix.
+    pub fn transform(&self, rows: &[Vec<f64>]) -> Array2<f64> {
+        let mut data = Vec::with_capacity(rows.len() * self.width());
+        for row in rows.iter() {
+            data.extend(self.normalise(row));
+        }
+        Array2::<f64>::from_shape_vec((rows.len(), self.width()), data).unwrap()
+    }
+}
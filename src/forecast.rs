@@ -0,0 +1,177 @@
+//! Next-day price forecasts from the predictive distribution of the transition model.
+//!
+//! Every training day carries the probability of the node it was won by, shared
+//! among the days of that node. The expected value and the quantiles of a bar
+//! column are taken over these weighted days and turned back into prices with
+//! the last close, as the bar features are relative to the last close.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::NaiveDate;
+#[cfg(not(feature = "serialize"))]
+use error_chain::bail;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+use crate::error_def::*;
+use crate::features::Pipeline;
+use crate::market::Market;
+use crate::markov::Node;
+
+/// Quantiles reported when no others are asked for.
+pub const DEFAULT_QUANTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+const FIELDS: [&str; 4] = ["open", "high", "low", "close"];
+
+/// Forecast of one price of one instrument.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct FieldForecast {
+    /// `open`, `high`, `low` or `close`.
+    pub field: String,
+    pub expected: f64,
+    /// One price per requested quantile, in the same order.
+    pub quantiles: Vec<f64>,
+}
+
+/// Forecast of the next bar of one instrument, made after the close of `day`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PriceForecast {
+    pub isin: String,
+    pub day: NaiveDate,
+    pub last_close: f64,
+    /// The quantiles of `FieldForecast::quantiles`, e.g. 0.05.
+    pub levels: Vec<f64>,
+    pub fields: Vec<FieldForecast>,
+}
+
+/// The `q` quantile of weighted values, `values` sorted by value.
+fn weighted_quantile(values: &[(f64, f64)], q: f64) -> f64 {
+    let total = values.iter().map(|(_, w)| w).sum::<f64>();
+    let mut acc = 0.0;
+    for (v, w) in values.iter() {
+        acc += w;
+        if acc >= q * total {
+            return *v;
+        }
+    }
+    values.last().map(|(v, _)| *v).unwrap_or(f64::NAN)
+}
+
+/// Forecast the next bar of every instrument with bar columns in the pipeline.
+///
+/// `rows` are the raw rows the `winners` were found for, `distribution` the
+/// predictive distribution over the nodes and `t` the common day of the last row.
+/// Instruments without `bar` feature are left out.
+pub fn forecast(
+    market: &Market,
+    pipeline: &Pipeline,
+    t: usize,
+    winners: &[Node],
+    rows: &[Vec<f64>],
+    distribution: &[(Node, f64)],
+    levels: &[f64],
+) -> Vec<PriceForecast> {
+    let mut node_days = HashMap::new();
+    for w in winners.iter() {
+        *node_days.entry(*w).or_insert(0usize) += 1;
+    }
+    let weights = winners
+        .iter()
+        .map(|w| {
+            distribution
+                .iter()
+                .find(|(node, _)| node == w)
+                .map(|(_, p)| p / node_days[w] as f64)
+                .unwrap_or(0.0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut forecasts = vec![];
+    for (i, isin) in market.isins.iter().enumerate() {
+        let last_close = market.bar(i, t).close;
+        let mut fields = vec![];
+        for field in FIELDS.iter() {
+            let name = format!("{}.{}", isin, field);
+            let col = match pipeline.names().iter().position(|n| *n == name) {
+                Some(col) => col,
+                None => continue,
+            };
+            let mut values = rows
+                .iter()
+                .zip(weights.iter())
+                .filter(|(_, w)| **w > 0.0)
+                .map(|(row, w)| (last_close * (1.0 + row[col]), *w))
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                continue;
+            }
+            values.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let total = values.iter().map(|(_, w)| w).sum::<f64>();
+            fields.push(FieldForecast {
+                field: field.to_string(),
+                expected: values.iter().map(|(v, w)| v * w).sum::<f64>() / total,
+                quantiles: levels
+                    .iter()
+                    .map(|q| weighted_quantile(&values, *q))
+                    .collect(),
+            });
+        }
+        if !fields.is_empty() {
+            forecasts.push(PriceForecast {
+                isin: isin.clone(),
+                day: market.day(t),
+                last_close,
+                levels: levels.to_vec(),
+                fields,
+            });
+        }
+    }
+    forecasts
+}
+
+/// One line per instrument and price: `isin,day,last_close,field,expected,q0.05,...`.
+pub fn write_csv<W: Write>(w: W, forecasts: &[PriceForecast]) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    if let Some(first) = forecasts.first() {
+        let mut header = vec!["isin", "day", "last_close", "field", "expected"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        header.extend(first.levels.iter().map(|q| format!("q{}", q)));
+        w.write_record(&header)?;
+    }
+    for f in forecasts.iter() {
+        for field in f.fields.iter() {
+            let mut record = vec![
+                f.isin.clone(),
+                f.day.to_string(),
+                f.last_close.to_string(),
+                field.field.clone(),
+                field.expected.to_string(),
+            ];
+            record.extend(field.quantiles.iter().map(|q| q.to_string()));
+            w.write_record(&record)?;
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Write the forecasts as CSV, or as JSON for a `.json` file.
+pub fn write_report(path: &Path, forecasts: &[PriceForecast]) -> Result<()> {
+    if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        #[cfg(feature = "serialize")]
+        return Ok(serde_json::to_writer_pretty(
+            File::create(path)?,
+            forecasts,
+        )?);
+        #[cfg(not(feature = "serialize"))]
+        bail!("JSON reports need the `serialize` feature: {:?}", path);
+    }
+    write_csv(File::create(path)?, forecasts)
+}
@@ -0,0 +1,347 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::error_def::*;
+use crate::locale::NumberLocale;
+use crate::ohlc::OHLC;
+
+/// A column of the imported file, by position (counting from 0) or by header name.
+#[derive(Clone, Debug)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Column> {
+        match s.parse() {
+            Ok(i) => Ok(Column::Index(i)),
+            _ => Ok(Column::Name(s.to_string())),
+        }
+    }
+}
+
+/// Header names tried for columns which are not given explicitly.
+const DATE_NAMES: &[&str] = &["date", "datum", "day", "tag"];
+const OPEN_NAMES: &[&str] = &["open", "eröffnung", "eroeffnung", "erster", "start"];
+const HIGH_NAMES: &[&str] = &["high", "hoch", "max", "tageshoch"];
+const LOW_NAMES: &[&str] = &["low", "tief", "min", "tagestief"];
+const VOLUME_NAMES: &[&str] = &["volume", "volumen", "stück", "stueck"];
+const CLOSE_NAMES: &[&str] = &[
+    "close",
+    "schluss",
+    "schlusskurs",
+    "letzter",
+    "adj close",
+    "kurs",
+];
+
+/// Date formats tried in order if none is given explicitly.
+/// `%Y` also takes two digits, so the two-digit years come first.
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d", "%d.%m.%y", "%d.%m.%Y", "%m/%d/%y", "%m/%d/%Y", "%Y%m%d",
+];
+
+/// Layout of a third-party CSV export.
+///
+/// Without explicit columns, files with headers are mapped by the usual
+/// English and German header names and files without by the internal order
+/// date, open, high, low, close, volume. Files lacking open, high or low use the
+/// close; the volume is optional.
+#[derive(Clone, Debug)]
+pub struct ImportConfig {
+    /// Field delimiter. If not given, the most frequent of `;`, `,`, tab and space
+    /// in the first line is used.
+    pub delimiter: Option<u8>,
+    pub has_headers: bool,
+    pub date: Option<Column>,
+    pub open: Option<Column>,
+    pub high: Option<Column>,
+    pub low: Option<Column>,
+    pub close: Option<Column>,
+    pub volume: Option<Column>,
+    pub date_format: Option<String>,
+    pub locale: NumberLocale,
+}
+
+impl Default for ImportConfig {
+    fn default() -> ImportConfig {
+        ImportConfig {
+            delimiter: None,
+            has_headers: true,
+            date: None,
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+            volume: None,
+            date_format: None,
+            locale: NumberLocale::English,
+        }
+    }
+}
+
+fn guess_delimiter(text: &str) -> u8 {
+    let first = text.lines().next().unwrap_or("");
+    let mut best = b',';
+    let mut best_cnt = 0;
+    for d in [b';', b',', b'\t', b' '].iter() {
+        let cnt = first.bytes().filter(|b| b == d).count();
+        if cnt > best_cnt {
+            best = *d;
+            best_cnt = cnt;
+        }
+    }
+    best
+}
+
+fn resolve(
+    column: &Option<Column>,
+    headers: &Option<Vec<String>>,
+    names: &[&str],
+    default: Option<usize>,
+) -> Result<Option<usize>> {
+    let find = |name: &str| match headers {
+        Some(headers) => headers
+            .iter()
+            .position(|h| h.trim().to_lowercase() == name.to_lowercase()),
+        None => None,
+    };
+    match column {
+        Some(Column::Index(i)) => Ok(Some(*i)),
+        Some(Column::Name(name)) => match find(name) {
+            Some(i) => Ok(Some(i)),
+            None => bail!("Column not found: {}", name),
+        },
+        None if headers.is_some() => Ok(names.iter().filter_map(|n| find(n)).next()),
+        None => Ok(default),
+    }
+}
+
+fn parse_date(s: &str, format: &Option<String>) -> Result<NaiveDate> {
+    let s = s.trim();
+    if let Some(format) = format {
+        return Ok(NaiveDate::parse_from_str(s, format)?);
+    }
+    for format in DATE_FORMATS.iter() {
+        if let Ok(day) = NaiveDate::parse_from_str(s, format) {
+            return Ok(day);
+        }
+    }
+    bail!("Unknown date format: {}", s)
+}
+
+impl ImportConfig {
+    /// Read all bars of an export, sorted by date.
+    /// Rows with an empty close, e.g. holidays in some exports, are skipped.
+    pub fn load<R: Read>(&self, mut rdr: R) -> Result<Vec<(NaiveDate, OHLC)>> {
+        let mut text = String::new();
+        rdr.read_to_string(&mut text)?;
+        let text = text.trim_start_matches('\u{feff}');
+        let delimiter = self.delimiter.unwrap_or_else(|| guess_delimiter(text));
+
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+
+        let headers = if self.has_headers {
+            Some(rdr.headers()?.iter().map(|h| h.to_string()).collect())
+        } else {
+            None
+        };
+        let date = match resolve(&self.date, &headers, DATE_NAMES, Some(0))? {
+            Some(i) => i,
+            None => bail!("No date column"),
+        };
+        let close = match resolve(&self.close, &headers, CLOSE_NAMES, Some(4))? {
+            Some(i) => i,
+            None => bail!("No close column"),
+        };
+        let open = resolve(&self.open, &headers, OPEN_NAMES, Some(1))?;
+        let high = resolve(&self.high, &headers, HIGH_NAMES, Some(2))?;
+        let low = resolve(&self.low, &headers, LOW_NAMES, Some(3))?;
+        let volume = resolve(&self.volume, &headers, VOLUME_NAMES, Some(5))?;
+
+        let mut ohlc_data = vec![];
+        for (line, result) in rdr.records().enumerate() {
+            let record = result?;
+            let field = |i: usize| record.get(i).unwrap_or("").trim();
+            if field(close).is_empty() {
+                continue;
+            }
+            let number = |i: usize| -> Result<f64> {
+                match self.locale.parse(field(i)) {
+                    Ok(x) => Ok(x),
+                    Err(_) => bail!(
+                        "Line {}: no number in column {}: {:?}",
+                        line + 1,
+                        i,
+                        field(i)
+                    ),
+                }
+            };
+            let day = parse_date(field(date), &self.date_format)?;
+            let close = number(close)?;
+            let or_close = |column: Option<usize>| -> Result<f64> {
+                match column {
+                    Some(i) if !field(i).is_empty() => number(i),
+                    _ => Ok(close),
+                }
+            };
+            let ohlc = OHLC {
+                open: or_close(open)?,
+                high: or_close(high)?,
+                low: or_close(low)?,
+                close,
+                volume: match volume {
+                    Some(i) if !field(i).is_empty() => Some(number(i)?),
+                    _ => None,
+                },
+            };
+            ohlc_data.push((day, ohlc));
+        }
+        ohlc_data.sort_by_key(|e| e.0);
+        Ok(ohlc_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn german_headers_and_separators() {
+        let text = "Datum;Erster;Hoch;Tief;Schlusskurs\n\
+                    31.10.2019;12.910,25;12.962,5;12.851,75;12.866,5\n\
+                    30.10.2019;12.880,5;12.930,25;12.850;12.910,25\n";
+        let config = ImportConfig {
+            locale: NumberLocale::German,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.load(text.as_bytes()).unwrap(),
+            vec![
+                (
+                    NaiveDate::from_ymd(2019, 10, 30),
+                    OHLC {
+                        open: 12880.5,
+                        high: 12930.25,
+                        low: 12850.0,
+                        close: 12910.25,
+                        volume: None,
+                    }
+                ),
+                (
+                    NaiveDate::from_ymd(2019, 10, 31),
+                    OHLC {
+                        open: 12910.25,
+                        high: 12962.5,
+                        low: 12851.75,
+                        close: 12866.5,
+                        volume: None,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn english_headers_in_any_order_and_separators() {
+        let text = "\u{feff}Date,Close,Low,High,Open\n\
+                    2019-10-31,\"1,234.5\",\"1,230.25\",\"1,240\",\"1,232.75\"\n\
+                    2019-11-01,,,,\n";
+        assert_eq!(
+            ImportConfig::default().load(text.as_bytes()).unwrap(),
+            vec![(
+                NaiveDate::from_ymd(2019, 10, 31),
+                OHLC {
+                    open: 1232.75,
+                    high: 1240.0,
+                    low: 1230.25,
+                    close: 1234.5,
+                    volume: None,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_prices_are_the_close() {
+        let text = "Tag\tKurs\n01/02/2019\t99.5\n";
+        assert_eq!(
+            ImportConfig::default().load(text.as_bytes()).unwrap(),
+            vec![(
+                NaiveDate::from_ymd(2019, 1, 2),
+                OHLC {
+                    open: 99.5,
+                    high: 99.5,
+                    low: 99.5,
+                    close: 99.5,
+                    volume: None,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn columns_by_position_without_headers() {
+        let text = "20191031 1232.75 1240 1230.25 1234.5\n";
+        let config = ImportConfig {
+            has_headers: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.load(text.as_bytes()).unwrap(),
+            vec![(
+                NaiveDate::from_ymd(2019, 10, 31),
+                OHLC {
+                    open: 1232.75,
+                    high: 1240.0,
+                    low: 1230.25,
+                    close: 1234.5,
+                    volume: None,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn two_digit_years() {
+        for (s, day) in &[
+            ("01.02.19", NaiveDate::from_ymd(2019, 2, 1)),
+            ("01.02.2019", NaiveDate::from_ymd(2019, 2, 1)),
+            ("31.12.99", NaiveDate::from_ymd(1999, 12, 31)),
+            ("01/02/19", NaiveDate::from_ymd(2019, 1, 2)),
+            ("01/02/2019", NaiveDate::from_ymd(2019, 1, 2)),
+        ] {
+            assert_eq!(parse_date(s, &None).unwrap(), *day, "{}", s);
+        }
+        let text = "Datum;Schluss\n01.02.19;12.866,5\n";
+        let config = ImportConfig {
+            locale: NumberLocale::German,
+            ..Default::default()
+        };
+        let bars = config.load(text.as_bytes()).unwrap();
+        assert_eq!(bars[0].0, NaiveDate::from_ymd(2019, 2, 1));
+        assert_eq!(bars[0].1.close, 12866.5);
+    }
+
+    #[test]
+    fn unknown_headers_fail() {
+        let text = "Zeit;Wert\n31.10.2019;1\n";
+        assert!(ImportConfig::default().load(text.as_bytes()).is_err());
+        let config = ImportConfig {
+            close: Some("Schluss".parse().unwrap()),
+            volume: None,
+            ..Default::default()
+        };
+        assert!(config.load("Datum;Wert\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Technical indicators over a series of values, usually closes.
+//!
+//! Every function returns a series of the same length as its input,
+//! with `None` where there is not enough history yet.
+
+/// Simple moving average over `n` values.
+pub fn sma(values: &[f64], n: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; values.len()];
+    if n == 0 {
+        return out;
+    }
+    let mut sum = 0.0;
+    for i in 0..values.len() {
+        sum += values[i];
+        if i >= n {
+            sum -= values[i - n];
+        }
+        if i + 1 >= n {
+            out[i] = Some(sum / n as f64);
+        }
+    }
+    out
+}
+
+/// Exponential moving average with smoothing 2/(n+1), seeded with the SMA of the first `n` values.
+pub fn ema(values: &[f64], n: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; values.len()];
+    if n == 0 || values.len() < n {
+        return out;
+    }
+    let k = 2.0 / (n as f64 + 1.0);
+    let mut e = values[..n].iter().sum::<f64>() / n as f64;
+    out[n - 1] = Some(e);
+    for i in n..values.len() {
+        e += k * (values[i] - e);
+        out[i] = Some(e);
+    }
+    out
+}
+
+/// Relative strength index (0..100) with Wilder's smoothing over `n` changes.
+pub fn rsi(values: &[f64], n: usize) -> Vec<Option<f64>> {
+    let mut out = vec![None; values.len()];
+    if n == 0 || values.len() <= n {
+        return out;
+    }
+    let mut gain = 0.0;
+    let mut loss = 0.0;
+    for i in 1..=n {
+        let d = values[i] - values[i - 1];
+        if d > 0.0 {
+            gain += d;
+        } else {
+            loss -= d;
+        }
+    }
+    gain /= n as f64;
+    loss /= n as f64;
+    let value = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+    out[n] = Some(value(gain, loss));
+    for i in n + 1..values.len() {
+        let d = values[i] - values[i - 1];
+        gain = (gain * (n - 1) as f64 + d.max(0.0)) / n as f64;
+        loss = (loss * (n - 1) as f64 + (-d).max(0.0)) / n as f64;
+        out[i] = Some(value(gain, loss));
+    }
+    out
+}
+
+/// MACD line, signal line and histogram, usually with (12, 26, 9).
+pub fn macd(
+    values: &[f64],
+    fast: usize,
+    slow: usize,
+    signal: usize,
+) -> Vec<Option<(f64, f64, f64)>> {
+    let fast = ema(values, fast);
+    let slow = ema(values, slow);
+    let line = fast
+        .iter()
+        .zip(slow.iter())
+        .map(|(f, s)| match (f, s) {
+            (Some(f), Some(s)) => Some(f - s),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = vec![None; values.len()];
+    let first = match line.iter().position(|l| l.is_some()) {
+        Some(first) => first,
+        None => return out,
+    };
+    let defined = line[first..].iter().map(|l| l.unwrap()).collect::<Vec<_>>();
+    for (i, s) in ema(&defined, signal).into_iter().enumerate() {
+        if let Some(s) = s {
+            let l = defined[i];
+            out[first + i] = Some((l, s, l - s));
+        }
+    }
+    out
+}
+
+/// Highest value of the last `n` values, including the current one.
+pub fn highest(values: &[f64], n: usize) -> Vec<Option<f64>> {
+    window(values, n, f64::max)
+}
+
+/// Lowest value of the last `n` values, including the current one.
+pub fn lowest(values: &[f64], n: usize) -> Vec<Option<f64>> {
+    window(values, n, f64::min)
+}
+
+fn window(values: &[f64], n: usize, f: fn(f64, f64) -> f64) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            if n == 0 || i + 1 < n {
+                None
+            } else {
+                values[i + 1 - n..=i].iter().cloned().fold(None, |acc, v| {
+                    Some(match acc {
+                        Some(a) => f(a, v),
+                        None => v,
+                    })
+                })
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::error_def::*;
+use crate::ohlc::Bar;
+use crate::store::{load_rows, save_rows, Row, Store};
+
+/// Write one JSON object per bar and line.
+pub fn write_jsonl<W: Write>(w: W, rows: &[Row]) -> Result<()> {
+    let mut w = BufWriter::new(w);
+    for row in rows.iter() {
+        let bar = Bar::from(row.clone());
+        serde_json::to_writer(&mut w, &bar)?;
+        writeln!(w)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Read bars written by `write_jsonl`. Empty lines are ignored.
+pub fn read_jsonl<R: BufRead>(r: R) -> Result<Vec<Row>> {
+    let mut rows = vec![];
+    for line in r.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            let bar: Bar = serde_json::from_str(&line)?;
+            rows.push(bar.into());
+        }
+    }
+    Ok(rows)
+}
+
+/// Export the given ISINs (all of the store if empty) into one JSON-lines file.
+/// Returns the number of bars written.
+pub fn export(store: &dyn Store, isins: &[String], path: &Path) -> Result<usize> {
+    let rows = load_rows(store, isins)?;
+    write_jsonl(File::create(path)?, &rows)?;
+    Ok(rows.len())
+}
+
+/// Read a JSON-lines file back into the store, replacing the histories of the ISINs it contains.
+/// Returns the ISINs written.
+pub fn import(store: &dyn Store, path: &Path) -> Result<Vec<String>> {
+    save_rows(store, read_jsonl(BufReader::new(File::open(path)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::ohlc::OHLC;
+
+    #[test]
+    fn round_trip() {
+        let rows = vec![
+            (
+                "DE0008469008".to_string(),
+                NaiveDate::from_ymd(2019, 10, 31),
+                OHLC {
+                    open: 0.1 + 0.2,
+                    high: 12962.5,
+                    low: 1e-7,
+                    close: 12866.79,
+                    volume: None,
+                },
+            ),
+            (
+                "US2605661048".to_string(),
+                NaiveDate::from_ymd(2019, 11, 1),
+                OHLC {
+                    open: 27061.56,
+                    high: 27347.36,
+                    low: 27061.56,
+                    close: 27347.36,
+                    volume: None,
+                },
+            ),
+        ];
+        let mut buf = vec![];
+        write_jsonl(&mut buf, &rows).unwrap();
+        assert_eq!(buf.iter().filter(|b| **b == b'\n').count(), 2);
+        assert_eq!(read_jsonl(&buf[..]).unwrap(), rows);
+    }
+
+    #[test]
+    fn malformed_lines_fail() {
+        let good =
+            r#"{"isin":"DE0008469008","day":"2019-10-31","open":1.5,"high":2,"low":1,"close":1.5}"#;
+        assert_eq!(
+            read_jsonl(format!("\n{}\n\n", good).as_bytes())
+                .unwrap()
+                .len(),
+            1
+        );
+        for bad in [
+            r#"{"isin":"DE0008469008","day":"31.10.2019","open":1.5,"high":2,"low":1,"close":1.5}"#,
+            r#"{"isin":"DE0008469008","day":"2019-10-31","open":1.5,"high":2,"low":1}"#,
+            r#"{"isin":"DE0008469008","day":"2019-10-31","open":"1,5","high":2,"low":1,"close":1.5}"#,
+            "DE0008469008 2019-10-31 1.5 2 1 1.5",
+        ]
+        .iter()
+        {
+            let text = format!("{}\n{}\n", good, bad);
+            assert!(read_jsonl(text.as_bytes()).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Actual holdings from a transaction file, valued with the closes of a store.
+//!
+//! The transaction file is CSV with the header `date,type,isin,quantity,price,fee`.
+//! `type` is `buy`, `sell`, `dividend` or `fee`; for dividends and fees `price`
+//! holds the cash amount and `quantity` may be empty.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::error_def::*;
+use crate::store::Store;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Buy,
+    Sell,
+    Dividend,
+    Fee,
+}
+
+impl FromStr for Kind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Kind> {
+        match s.to_lowercase().as_str() {
+            "buy" => Ok(Kind::Buy),
+            "sell" => Ok(Kind::Sell),
+            "dividend" => Ok(Kind::Dividend),
+            "fee" => Ok(Kind::Fee),
+            _ => bail!("Unknown transaction type: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub day: NaiveDate,
+    pub kind: Kind,
+    pub isin: String,
+    pub quantity: f64,
+    /// Price per unit, or the amount of a dividend or fee.
+    pub price: f64,
+    pub fee: f64,
+}
+
+/// How the cost of sold units is determined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CostMethod {
+    /// The units bought first are sold first.
+    Fifo,
+    /// All units held cost the same.
+    Average,
+}
+
+impl FromStr for CostMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CostMethod> {
+        match s {
+            "fifo" => Ok(CostMethod::Fifo),
+            "average" => Ok(CostMethod::Average),
+            _ => bail!("Unknown cost method: {}", s),
+        }
+    }
+}
+
+/// Transactions of a file sorted by day, keeping the file order within a day.
+pub fn read_transactions<R: Read>(r: R) -> Result<Vec<Transaction>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(r);
+    let mut transactions = vec![];
+    for (line, result) in rdr.records().enumerate() {
+        let record = result?;
+        let field = |i: usize| record.get(i).unwrap_or("");
+        let number = |i: usize| -> Result<f64> {
+            match field(i) {
+                "" => Ok(0.0),
+                s => Ok(s.parse()?),
+            }
+        };
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        let kind = field(1).parse::<Kind>()?;
+        let transaction = Transaction {
+            day: NaiveDate::parse_from_str(field(0), "%Y-%m-%d")?,
+            kind,
+            isin: field(2).to_string(),
+            quantity: number(3)?,
+            price: number(4)?,
+            fee: number(5)?,
+        };
+        if (kind == Kind::Buy || kind == Kind::Sell) && transaction.quantity <= 0.0 {
+            bail!("Line {}: {:?} without positive quantity", line + 2, kind);
+        }
+        transactions.push(transaction);
+    }
+    transactions.sort_by_key(|t| t.day);
+    Ok(transactions)
+}
+
+/// The holding of one ISIN.
+#[derive(Clone, Debug, Default)]
+pub struct Position {
+    pub isin: String,
+    pub quantity: f64,
+    /// What the units held cost, fees of the buys included.
+    pub cost: f64,
+    /// Proceeds of sales minus their cost and fees.
+    pub realised: f64,
+    pub dividends: f64,
+    /// Fees not belonging to a buy or sale.
+    pub fees: f64,
+    /// Units held with their cost per unit, oldest first. Only used for FIFO.
+    lots: VecDeque<(f64, f64)>,
+}
+
+impl Position {
+    pub fn average_price(&self) -> f64 {
+        if self.quantity > 0.0 {
+            self.cost / self.quantity
+        } else {
+            0.0
+        }
+    }
+
+    pub fn unrealised(&self, price: f64) -> f64 {
+        self.quantity * price - self.cost
+    }
+
+    fn apply(&mut self, t: &Transaction, method: CostMethod) -> Result<()> {
+        match t.kind {
+            Kind::Buy => {
+                let cost = t.quantity * t.price + t.fee;
+                self.quantity += t.quantity;
+                self.cost += cost;
+                self.lots.push_back((t.quantity, cost / t.quantity));
+            }
+            Kind::Sell => {
+                // Allow for rounding of fractional units.
+                if t.quantity > self.quantity + 1e-9 {
+                    bail!(
+                        "{}: selling {} {} but holding {}",
+                        t.day,
+                        t.quantity,
+                        t.isin,
+                        self.quantity
+                    );
+                }
+                let sold_cost = match method {
+                    CostMethod::Average => self.average_price() * t.quantity,
+                    CostMethod::Fifo => {
+                        let mut left = t.quantity;
+                        let mut cost = 0.0;
+                        while left > 1e-9 {
+                            // Lots run out before `left` only by the rounding allowed above.
+                            let lot = match self.lots.front_mut() {
+                                Some(lot) => lot,
+                                None => break,
+                            };
+                            let take = lot.0.min(left);
+                            cost += take * lot.1;
+                            lot.0 -= take;
+                            left -= take;
+                            if lot.0 <= 1e-9 {
+                                self.lots.pop_front();
+                            }
+                        }
+                        cost
+                    }
+                };
+                self.quantity -= t.quantity;
+                self.cost -= sold_cost;
+                self.realised += t.quantity * t.price - t.fee - sold_cost;
+                if self.quantity <= 1e-9 {
+                    self.quantity = 0.0;
+                    self.cost = 0.0;
+                    self.lots.clear();
+                }
+            }
+            Kind::Dividend => self.dividends += t.price - t.fee,
+            Kind::Fee => self.fees += t.price + t.fee,
+        }
+        Ok(())
+    }
+}
+
+/// Replays transactions into positions.
+pub struct Ledger {
+    method: CostMethod,
+    positions: BTreeMap<String, Position>,
+}
+
+impl Ledger {
+    pub fn new(method: CostMethod) -> Ledger {
+        Ledger {
+            method,
+            positions: BTreeMap::new(),
+        }
+    }
+
+    pub fn apply(&mut self, t: &Transaction) -> Result<()> {
+        let position = self
+            .positions
+            .entry(t.isin.clone())
+            .or_insert_with(|| Position {
+                isin: t.isin.clone(),
+                ..Default::default()
+            });
+        position.apply(t, self.method)
+    }
+
+    /// All positions ever held, sorted by ISIN.
+    pub fn positions(&self) -> impl Iterator<Item = &Position> {
+        self.positions.values()
+    }
+}
+
+/// The positions after all transactions up to and including `until`.
+pub fn positions(
+    transactions: &[Transaction],
+    method: CostMethod,
+    until: Option<NaiveDate>,
+) -> Result<Vec<Position>> {
+    let mut ledger = Ledger::new(method);
+    for t in transactions
+        .iter()
+        .filter(|t| until.map(|u| t.day <= u).unwrap_or(true))
+    {
+        ledger.apply(t)?;
+    }
+    Ok(ledger.positions().cloned().collect())
+}
+
+/// The portfolio at the close of one day.
+#[derive(Clone, Debug)]
+pub struct Valuation {
+    pub day: NaiveDate,
+    /// Units held times their last close on or before the day.
+    pub market_value: f64,
+    pub cost: f64,
+    pub realised: f64,
+    pub dividends: f64,
+    pub fees: f64,
+}
+
+impl Valuation {
+    /// Unrealised and realised gains plus dividends, minus other fees.
+    pub fn profit(&self) -> f64 {
+        self.market_value - self.cost + self.realised + self.dividends - self.fees
+    }
+}
+
+/// Last close on or before every day with a close of any traded ISIN, from the first transaction on.
+pub fn valuation(
+    store: &dyn Store,
+    transactions: &[Transaction],
+    method: CostMethod,
+) -> Result<Vec<Valuation>> {
+    let first = match transactions.first() {
+        Some(t) => t.day,
+        None => return Ok(vec![]),
+    };
+    let mut closes = HashMap::new();
+    let mut days = BTreeSet::new();
+    for isin in transactions
+        .iter()
+        .filter(|t| t.kind == Kind::Buy)
+        .map(|t| &t.isin)
+    {
+        if !closes.contains_key(isin) {
+            let history = store
+                .load(isin)?
+                .into_iter()
+                .map(|(day, ohlc)| (day, ohlc.close))
+                .collect::<BTreeMap<_, _>>();
+            days.extend(history.keys().filter(|d| **d >= first).cloned());
+            closes.insert(isin.clone(), history);
+        }
+    }
+
+    let mut ledger = Ledger::new(method);
+    let mut pending = transactions.iter().peekable();
+    let mut valuations = vec![];
+    for day in days.into_iter() {
+        while let Some(t) = pending.peek() {
+            if t.day > day {
+                break;
+            }
+            ledger.apply(t)?;
+            pending.next();
+        }
+        let mut v = Valuation {
+            day,
+            market_value: 0.0,
+            cost: 0.0,
+            realised: 0.0,
+            dividends: 0.0,
+            fees: 0.0,
+        };
+        for p in ledger.positions() {
+            if p.quantity > 0.0 {
+                match closes
+                    .get(&p.isin)
+                    .and_then(|h| h.range(..=day).next_back())
+                {
+                    Some((_, close)) => v.market_value += p.quantity * close,
+                    None => bail!("No close of {} on or before {}", p.isin, day),
+                }
+            }
+            v.cost += p.cost;
+            v.realised += p.realised;
+            v.dividends += p.dividends;
+            v.fees += p.fees;
+        }
+        valuations.push(v);
+    }
+    Ok(valuations)
+}
+
+/// `day,market_value,cost,realised,dividends,fees,profit` per line.
+pub fn write_valuation<W: Write>(w: W, valuations: &[Valuation]) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    w.write_record([
+        "day",
+        "market_value",
+        "cost",
+        "realised",
+        "dividends",
+        "fees",
+        "profit",
+    ])?;
+    for v in valuations.iter() {
+        w.write_record(&[
+            v.day.to_string(),
+            v.market_value.to_string(),
+            v.cost.to_string(),
+            v.realised.to_string(),
+            v.dividends.to_string(),
+            v.fees.to_string(),
+            v.profit().to_string(),
+        ])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn fifo_sell_spans_lots() {
+        let csv = "date,type,isin,quantity,price,fee
+                   2019-01-02,buy,DE0007164600,10,50,5
+                   2019-01-03,buy,DE0007164600,10,60,5
+                   2019-01-04,sell,DE0007164600,15,70,5";
+        let transactions = read_transactions(csv.as_bytes()).unwrap();
+        let p = positions(&transactions, CostMethod::Fifo, None)
+            .unwrap()
+            .remove(0);
+        assert_close(p.quantity, 5.0);
+        // All of the first lot at 50.5 and half of the second at 60.5.
+        assert_close(p.cost, 5.0 * 60.5);
+        assert_close(p.realised, 15.0 * 70.0 - 5.0 - (10.0 * 50.5 + 5.0 * 60.5));
+        assert_eq!(p.lots.len(), 1);
+        assert_close(p.lots[0].0, 5.0);
+    }
+
+    #[test]
+    fn average_sell_spans_lots() {
+        let csv = "date,type,isin,quantity,price,fee
+                   2019-01-02,buy,DE0007164600,10,50,5
+                   2019-01-03,buy,DE0007164600,10,60,5
+                   2019-01-04,sell,DE0007164600,15,70,5";
+        let transactions = read_transactions(csv.as_bytes()).unwrap();
+        let p = positions(&transactions, CostMethod::Average, None)
+            .unwrap()
+            .remove(0);
+        assert_close(p.quantity, 5.0);
+        assert_close(p.cost, 5.0 * 55.5);
+        assert_close(p.average_price(), 55.5);
+        assert_close(p.realised, 15.0 * 70.0 - 5.0 - 15.0 * 55.5);
+    }
+
+    #[test]
+    fn selling_more_than_held_fails() {
+        let csv = "date,type,isin,quantity,price,fee
+                   2019-01-02,buy,DE0007164600,10,50,5
+                   2019-01-03,buy,DE0007164600,10,60,5
+                   2019-01-04,sell,DE0007164600,25,70,0";
+        let transactions = read_transactions(csv.as_bytes()).unwrap();
+        for method in [CostMethod::Fifo, CostMethod::Average].iter() {
+            assert!(positions(&transactions, *method, None).is_err());
+        }
+    }
+
+    #[test]
+    fn fifo_rounding_residue_does_not_panic() {
+        // Each sell leaves a residue of its lot small enough to be dropped.
+        let csv = "date,type,isin,quantity,price,fee
+                   2019-01-02,buy,DE0007164600,1,50,0
+                   2019-01-02,buy,DE0007164600,1,50,0
+                   2019-01-02,buy,DE0007164600,1,50,0
+                   2019-01-03,sell,DE0007164600,0.999999999,60,0
+                   2019-01-04,sell,DE0007164600,0.999999999,60,0
+                   2019-01-07,sell,DE0007164600,1.000000002,60,0";
+        let transactions = read_transactions(csv.as_bytes()).unwrap();
+        let p = positions(&transactions, CostMethod::Fifo, None)
+            .unwrap()
+            .remove(0);
+        assert_eq!(p.quantity, 0.0);
+        assert!(p.lots.is_empty());
+    }
+
+    #[test]
+    fn full_close_resets_cost() {
+        let csv = "date,type,isin,quantity,price,fee
+                   2019-01-02,buy,DE0007164600,10,50,5
+                   2019-01-03,buy,DE0007164600,10,60,5
+                   2019-01-04,sell,DE0007164600,20,70,0
+                   2019-01-07,buy,DE0007164600,2,80,0";
+        let transactions = read_transactions(csv.as_bytes()).unwrap();
+        let closed = NaiveDate::from_ymd(2019, 1, 4);
+        for method in [CostMethod::Fifo, CostMethod::Average].iter() {
+            let p = positions(&transactions, *method, Some(closed))
+                .unwrap()
+                .remove(0);
+            assert_eq!(p.quantity, 0.0);
+            assert_eq!(p.cost, 0.0);
+            assert!(p.lots.is_empty());
+            assert_close(p.realised, 20.0 * 70.0 - 1110.0);
+
+            // A new buy starts from scratch.
+            let p = positions(&transactions, *method, None).unwrap().remove(0);
+            assert_close(p.average_price(), 80.0);
+        }
+    }
+}
@@ -1,4 +1,33 @@
+pub mod alert;
+pub mod align;
+pub mod analog;
+pub mod backtest;
+pub mod chart;
+pub mod cli;
+pub mod cluster;
+pub mod columnar;
+pub mod daily_report;
 pub mod error_def;
+pub mod features;
+pub mod forecast;
+pub mod import;
+pub mod indicators;
+#[cfg(feature = "serialize")]
+pub mod jsonl;
+pub mod ledger;
+pub mod locale;
+pub mod market;
+pub mod markov;
+pub mod model;
+pub mod node_stats;
 pub mod ohlc;
+pub mod ohlcx;
+pub mod risk;
+pub mod screen;
+pub mod som;
+pub mod som_plot;
+pub mod sqlite_store;
+pub mod store;
+pub mod walkforward;
 
 pub use ohlc::OHLC;
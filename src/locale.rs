@@ -0,0 +1,36 @@
+use std::str::FromStr;
+
+use error_chain::bail;
+
+use crate::error_def::*;
+
+/// How numbers are written: `1.234,56` (German) or `1,234.56` (English).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberLocale {
+    German,
+    English,
+}
+
+impl FromStr for NumberLocale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<NumberLocale> {
+        match s.to_lowercase().as_str() {
+            "de" | "german" => Ok(NumberLocale::German),
+            "en" | "english" => Ok(NumberLocale::English),
+            _ => bail!("Unknown number locale: {}", s),
+        }
+    }
+}
+
+impl NumberLocale {
+    /// Parse a number with optional thousands separators.
+    pub fn parse(self, s: &str) -> Result<f64> {
+        let s = s.trim();
+        let s = match self {
+            NumberLocale::German => s.replace('.', "").replace(',', "."),
+            NumberLocale::English => s.replace(',', ""),
+        };
+        Ok(s.parse()?)
+    }
+}
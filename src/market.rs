@@ -0,0 +1,78 @@
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::align::align;
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+use crate::store::Store;
+
+/// ISINs of a config file, one per line. Empty lines and lines starting with `#` are ignored.
+pub fn read_isins(fname: &str) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(fname)?;
+    Ok(text
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// The histories of several instruments and the days on which all of them traded.
+pub struct Market {
+    pub isins: Vec<String>,
+    pub histories: Vec<Vec<(NaiveDate, OHLC)>>,
+    /// Common days, with the position of the day's bar in each history.
+    pub days: Vec<(NaiveDate, Vec<usize>)>,
+}
+
+impl Market {
+    pub fn new(isins: Vec<String>, histories: Vec<Vec<(NaiveDate, OHLC)>>) -> Market {
+        let positions = histories
+            .iter()
+            .map(|h| h.iter().enumerate().map(|(i, e)| (e.0, i)).collect())
+            .collect();
+        let days = align(positions);
+        Market {
+            isins,
+            histories,
+            days,
+        }
+    }
+
+    pub fn load(store: &dyn Store, isins: &[String]) -> Result<Market> {
+        let mut histories = vec![];
+        for isin in isins.iter() {
+            let history = store.load(isin)?;
+            if history.is_empty() {
+                bail!("No data for {}", isin);
+            }
+            histories.push(history);
+        }
+        Ok(Market::new(isins.to_vec(), histories))
+    }
+
+    /// The bars of instrument `i` up to and including common day `t`, latest last.
+    pub fn history(&self, i: usize, t: usize) -> &[(NaiveDate, OHLC)] {
+        &self.histories[i][..=self.days[t].1[i]]
+    }
+
+    /// The bar of instrument `i` on common day `t`.
+    pub fn bar(&self, i: usize, t: usize) -> &OHLC {
+        &self.histories[i][self.days[t].1[i]].1
+    }
+
+    pub fn day(&self, t: usize) -> NaiveDate {
+        self.days[t].0
+    }
+
+    /// Position of an instrument given by its ISIN or index.
+    pub fn instrument(&self, s: &str) -> Result<usize> {
+        if let Some(i) = self.isins.iter().position(|isin| isin == s) {
+            return Ok(i);
+        }
+        match s.parse::<usize>() {
+            Ok(i) if i < self.isins.len() => Ok(i),
+            _ => bail!("Unknown instrument: {}", s),
+        }
+    }
+}
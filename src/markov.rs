@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use error_chain::bail;
+use ndarray::{Array1, Array2};
+
+use crate::error_def::*;
+
+/// Transition counts from the last `order` states of a sequence, e.g. SOM winners,
+/// to the state following them.
+pub struct Transitions<S: Hash + Eq> {
+    order: usize,
+    counts: HashMap<Vec<S>, HashMap<S, usize>>,
+}
+
+impl<S: Hash + Eq + Clone> Transitions<S> {
+    /// Order 0 counts how often each state occurs, with the empty context.
+    pub fn from_sequence(states: &[S], order: usize) -> Transitions<S> {
+        let mut counts = HashMap::new();
+        if states.len() > order {
+            for w in states.windows(order + 1) {
+                let e0 = counts
+                    .entry(w[..order].to_vec())
+                    .or_insert_with(HashMap::new);
+                *e0.entry(w[order].clone()).or_insert(0) += 1;
+            }
+        }
+        Transitions { order, counts }
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// How often each state followed `context`, which has `order` states.
+    pub fn successors(&self, context: &[S]) -> Option<&HashMap<S, usize>> {
+        self.counts.get(context)
+    }
+
+    /// All contexts with their successor counts.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<S>, &HashMap<S, usize>)> {
+        self.counts.iter()
+    }
+}
+
+/// The mean row of `data` for every state, `states[i]` being the state of row `i`.
+pub fn state_means<S: Hash + Eq + Clone>(
+    states: &[S],
+    data: &Array2<f64>,
+) -> HashMap<S, Array1<f64>> {
+    let mut sums = HashMap::new();
+    for (state, v) in states.iter().zip(data.outer_iter()) {
+        let e = sums
+            .entry(state.clone())
+            .or_insert_with(|| (Array1::<f64>::zeros(data.cols()), 0));
+        e.0 = &e.0 + &v;
+        e.1 += 1;
+    }
+    sums.into_iter()
+        .map(|(state, (sum, n))| (state, sum / n as f64))
+        .collect()
+}
+
+/// How the transition probabilities are estimated from the counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Relative frequencies. States never seen after a context get probability zero.
+    None,
+    /// Add the pseudo count to every state, `laplace:1` being Laplace's rule.
+    Laplace(f64),
+    /// Interpolated Kneser-Ney with absolute discount, `kn:0.75` by default.
+    /// The lowest order uses how many different states a state followed instead of its frequency.
+    KneserNey(f64),
+}
+
+impl FromStr for Smoothing {
+    type Err = Error;
+
+    /// `none`, `laplace[:PSEUDO_COUNT]` or `kn[:DISCOUNT]`.
+    fn from_str(s: &str) -> Result<Smoothing> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let value = match parts.next() {
+            Some(v) => Some(v.parse::<f64>()?),
+            None => None,
+        };
+        match (name, value) {
+            ("none", None) => Ok(Smoothing::None),
+            ("laplace", v) => Ok(Smoothing::Laplace(v.unwrap_or(1.0))),
+            ("kn", Some(d)) if !(0.0..=1.0).contains(&d) => {
+                bail!("Kneser-Ney discount not in 0..1: {}", d)
+            }
+            ("kn", v) => Ok(Smoothing::KneserNey(v.unwrap_or(0.75))),
+            _ => bail!("Unknown smoothing: {}", s),
+        }
+    }
+}
+
+/// Settings of a `TransitionModel`.
+#[derive(Clone, Debug)]
+pub struct MarkovSettings {
+    /// Number of past winners the next one is conditioned on.
+    pub order: usize,
+    pub smoothing: Smoothing,
+    /// Pool the counts of contexts whose nodes lie at most this many grid steps
+    /// (in rows or columns) away, weighted by `1 / (1 + steps)`. 0 pools nothing.
+    pub radius: usize,
+    /// Contexts followed fewer times, after pooling, back off to the next lower order.
+    pub min_count: usize,
+}
+
+impl Default for MarkovSettings {
+    fn default() -> MarkovSettings {
+        MarkovSettings {
+            order: 1,
+            smoothing: Smoothing::None,
+            radius: 0,
+            min_count: 1,
+        }
+    }
+}
+
+/// A node of the SOM grid, `(row, col)`.
+pub type Node = (usize, usize);
+
+fn grid_steps(a: &[Node], b: &[Node]) -> usize {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| x.0.abs_diff(y.0).max(x.1.abs_diff(y.1)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// A transition model over SOM winners of up to `settings.order` with backoff:
+/// contexts seen too rarely fall back to shorter contexts, down to the plain
+/// frequency of the nodes.
+pub struct TransitionModel {
+    settings: MarkovSettings,
+    /// `levels[k]` has the counts of order `k`.
+    levels: Vec<Transitions<Node>>,
+    /// Nodes won in the training sequence, sorted. The distribution is over these.
+    nodes: Vec<Node>,
+    /// For every node the number of different nodes it followed, for Kneser-Ney.
+    continuations: HashMap<Node, f64>,
+}
+
+impl TransitionModel {
+    pub fn fit(winners: &[Node], settings: &MarkovSettings) -> TransitionModel {
+        let levels = (0..=settings.order)
+            .map(|k| Transitions::from_sequence(winners, k))
+            .collect::<Vec<_>>();
+        let mut nodes = winners.to_vec();
+        nodes.sort();
+        nodes.dedup();
+        let mut continuations = HashMap::new();
+        if let Some(first) = levels.get(1) {
+            for (_, successors) in first.iter() {
+                for node in successors.keys() {
+                    *continuations.entry(*node).or_insert(0.0) += 1.0;
+                }
+            }
+        }
+        TransitionModel {
+            settings: settings.clone(),
+            levels,
+            nodes,
+            continuations,
+        }
+    }
+
+    pub fn settings(&self) -> &MarkovSettings {
+        &self.settings
+    }
+
+    /// Successor counts of `context` at order `context.len()`, pooled over its grid neighbourhood.
+    pub fn pooled_counts(&self, context: &[Node]) -> HashMap<Node, f64> {
+        let mut pooled = HashMap::new();
+        let level = match self.levels.get(context.len()) {
+            Some(level) => level,
+            None => return pooled,
+        };
+        let mut add = |successors: &HashMap<Node, usize>, weight: f64| {
+            for (node, cnt) in successors.iter() {
+                *pooled.entry(*node).or_insert(0.0) += weight * *cnt as f64;
+            }
+        };
+        if self.settings.radius == 0 || context.is_empty() {
+            if let Some(successors) = level.successors(context) {
+                add(successors, 1.0);
+            }
+        } else {
+            for (other, successors) in level.iter() {
+                let steps = grid_steps(context, other);
+                if steps <= self.settings.radius {
+                    add(successors, 1.0 / (1.0 + steps as f64));
+                }
+            }
+        }
+        pooled
+    }
+
+    /// Probabilities of all nodes given the last `k` nodes of `history`.
+    fn probabilities(&self, history: &[Node], k: usize) -> HashMap<Node, f64> {
+        let v = self.nodes.len() as f64;
+        let context = &history[history.len() - k..];
+        let counts = self.pooled_counts(context);
+        let total = counts.values().sum::<f64>();
+        if k > 0 && (total == 0.0 || total < self.settings.min_count as f64) {
+            return self.probabilities(history, k - 1);
+        }
+        let count = |node: &Node| counts.get(node).cloned().unwrap_or(0.0);
+
+        match self.settings.smoothing {
+            Smoothing::None => self.nodes.iter().map(|n| (*n, count(n) / total)).collect(),
+            Smoothing::Laplace(alpha) => self
+                .nodes
+                .iter()
+                .map(|n| (*n, (count(n) + alpha) / (total + alpha * v)))
+                .collect(),
+            Smoothing::KneserNey(_) if k == 0 => {
+                let cont_total = self.continuations.values().sum::<f64>();
+                if cont_total == 0.0 {
+                    self.nodes.iter().map(|n| (*n, count(n) / total)).collect()
+                } else {
+                    self.nodes
+                        .iter()
+                        .map(|n| {
+                            (
+                                *n,
+                                self.continuations.get(n).cloned().unwrap_or(0.0) / cont_total,
+                            )
+                        })
+                        .collect()
+                }
+            }
+            Smoothing::KneserNey(discount) => {
+                let lower = self.probabilities(history, k - 1);
+                // The mass taken by the discount goes to the lower order. Pooled counts
+                // can be below the discount, those give only what they have.
+                let backoff = counts.values().map(|c| c.min(discount)).sum::<f64>() / total;
+                self.nodes
+                    .iter()
+                    .map(|n| {
+                        let p = (count(n) - discount).max(0.0) / total + backoff * lower[n];
+                        (*n, p)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Predictive distribution of the node following `history`, most probable first.
+    /// Nodes with probability zero are left out.
+    pub fn distribution(&self, history: &[Node]) -> Vec<(Node, f64)> {
+        if self.nodes.is_empty() {
+            return vec![];
+        }
+        let k = self.settings.order.min(history.len());
+        let mut dist = self
+            .probabilities(history, k)
+            .into_iter()
+            .filter(|(_, p)| *p > 0.0)
+            .collect::<Vec<_>>();
+        dist.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        dist
+    }
+
+    /// The expected next row: the mean rows of the nodes weighted by `distribution`.
+    /// `None` if no node with a mean row has a probability.
+    pub fn estimate(
+        &self,
+        history: &[Node],
+        means: &HashMap<Node, Array1<f64>>,
+    ) -> Option<Array1<f64>> {
+        let mut est: Option<Array1<f64>> = None;
+        let mut weight = 0.0;
+        for (node, p) in self.distribution(history) {
+            if let Some(mean) = means.get(&node) {
+                let weighted = mean * p;
+                est = Some(match est {
+                    Some(e) => e + weighted,
+                    None => weighted,
+                });
+                weight += p;
+            }
+        }
+        est.map(|e| e / weight)
+    }
+}
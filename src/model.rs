@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::cluster::{load_clusterer, Clusterer};
+use crate::error_def::*;
+
+/// A trained clustering model together with everything needed to score new days:
+/// the instruments, the feature pipeline and its normalisation, and the training data range.
+///
+/// Saved as a text file of `key value` lines.
+pub struct Model {
+    pub clusterer: Box<dyn Clusterer>,
+    pub isins: Vec<String>,
+    /// Definition of the feature pipeline, see `features::Pipeline::parse`.
+    pub features: String,
+    /// Normalisation parameters of the pipeline, see `features::Pipeline::params`.
+    pub params: Vec<(f64, f64)>,
+    pub train_from: NaiveDate,
+    pub train_to: NaiveDate,
+    pub seed: u64,
+    pub iterations: u32,
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_all<T: std::str::FromStr>(s: &str) -> Result<Vec<T>> {
+    let mut values = vec![];
+    for v in s.split_whitespace() {
+        match v.parse() {
+            Ok(v) => values.push(v),
+            _ => bail!("Not a number in model: {}", v),
+        }
+    }
+    Ok(values)
+}
+
+impl Model {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let params = self
+            .params
+            .iter()
+            .flat_map(|(o, f)| vec![*o, *f])
+            .collect::<Vec<_>>();
+
+        let mut f = File::create(path)?;
+        writeln!(f, "method {}", self.clusterer.kind())?;
+        writeln!(f, "isins {}", self.isins.join(" "))?;
+        writeln!(f, "features {}", self.features.replace('\n', ";"))?;
+        writeln!(f, "params {}", join(&params))?;
+        writeln!(f, "train_from {}", self.train_from)?;
+        writeln!(f, "train_to {}", self.train_to)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "iterations {}", self.iterations)?;
+        self.clusterer.save(&mut f)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Model> {
+        let mut values = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let mut kv = line.splitn(2, ' ');
+            let key = kv.next().unwrap_or("");
+            let value = kv.next().unwrap_or("").trim();
+            if !key.is_empty() {
+                values.insert(key.to_string(), value.to_string());
+            }
+        }
+        let value = |key: &str| values.get(key).map(|s| s.as_str());
+
+        let isins = value("isins")
+            .unwrap_or("")
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect();
+        let params = parse_all::<f64>(value("params").unwrap_or(""))?;
+        let (features, train_from, train_to) =
+            match (value("features"), value("train_from"), value("train_to")) {
+                (Some(f), Some(from), Some(to)) => (
+                    f.to_string(),
+                    NaiveDate::parse_from_str(from, "%Y-%m-%d")?,
+                    NaiveDate::parse_from_str(to, "%Y-%m-%d")?,
+                ),
+                _ => bail!("Incomplete model: {:?}", path),
+            };
+        let clusterer = match load_clusterer(&values) {
+            Ok(clusterer) => clusterer,
+            Err(e) => bail!("{}: {:?}", e, path),
+        };
+        Ok(Model {
+            clusterer,
+            isins,
+            features,
+            params: params.chunks(2).map(|p| (p[0], p[1])).collect(),
+            train_from,
+            train_to,
+            seed: value("seed").unwrap_or("0").parse()?,
+            iterations: value("iterations").unwrap_or("0").parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::Rng;
+
+    use super::*;
+    use crate::cluster::Method;
+    use crate::som::seeded_rng;
+
+    #[test]
+    fn saved_som_reloads_identically() {
+        let mut rng = seeded_rng(1);
+        let data = Array2::from_shape_fn((50, 5), |_| rng.gen::<f64>());
+        let mut rng = seeded_rng(42);
+        let mut som = Method::Som(4, 3).build(5, &mut rng);
+        som.fit(&data, 500, &mut rng);
+        let mut rng = seeded_rng(42);
+        let mut again = Method::Som(4, 3).build(5, &mut rng);
+        again.fit(&data, 500, &mut rng);
+        assert_eq!(
+            som.as_som().unwrap().weights(),
+            again.as_som().unwrap().weights()
+        );
+
+        let model = Model {
+            clusterer: som,
+            isins: vec!["DE0008469008".to_string()],
+            features: "weekday; bar(*) scale(20)".to_string(),
+            params: vec![(0.5, 2.0); 5],
+            train_from: NaiveDate::from_ymd(2015, 1, 1),
+            train_to: NaiveDate::from_ymd(2019, 12, 31),
+            seed: 42,
+            iterations: 500,
+        };
+        let path = std::env::temp_dir().join(format!("som-{}.model", std::process::id()));
+        model.save(&path).unwrap();
+        let loaded = Model::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (saved, loaded_som) = (
+            model.clusterer.as_som().unwrap(),
+            loaded.clusterer.as_som().unwrap(),
+        );
+        assert_eq!(saved.weights(), loaded_som.weights());
+        for v in data.outer_iter() {
+            assert_eq!(saved.winner(v), loaded_som.winner(v));
+        }
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.params, model.params);
+        assert_eq!(loaded.features, model.features);
+    }
+}
@@ -0,0 +1,162 @@
+//! What followed the days won by each node of the map: per node the member days
+//! and the distribution of the returns of one instrument over the next days.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use chrono::NaiveDate;
+
+use crate::error_def::*;
+use crate::market::Market;
+use crate::markov::Node;
+
+/// Next day and next week, in common days.
+pub const DEFAULT_HORIZONS: [usize; 2] = [1, 5];
+
+/// Close-to-close returns over one horizon after the days of a node.
+#[derive(Clone, Debug)]
+pub struct ReturnStats {
+    pub horizon: usize,
+    /// Days with the horizon still inside the history.
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Standard deviation of the returns.
+    pub volatility: f64,
+    /// Share of positive returns.
+    pub up: f64,
+    /// Day and return of the best and worst outcome.
+    pub best: (NaiveDate, f64),
+    pub worst: (NaiveDate, f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeStats {
+    pub node: Node,
+    pub days: Vec<NaiveDate>,
+    /// One entry per horizon with at least one return.
+    pub returns: Vec<ReturnStats>,
+}
+
+fn return_stats(horizon: usize, mut returns: Vec<(NaiveDate, f64)>) -> Option<ReturnStats> {
+    if returns.is_empty() {
+        return None;
+    }
+    let n = returns.len();
+    let mean = returns.iter().map(|r| r.1).sum::<f64>() / n as f64;
+    let var = returns
+        .iter()
+        .map(|r| (r.1 - mean) * (r.1 - mean))
+        .sum::<f64>()
+        / n as f64;
+    let up = returns.iter().filter(|r| r.1 > 0.0).count() as f64 / n as f64;
+    returns.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let median = if n % 2 == 1 {
+        returns[n / 2].1
+    } else {
+        (returns[n / 2 - 1].1 + returns[n / 2].1) / 2.0
+    };
+    Some(ReturnStats {
+        horizon,
+        n,
+        mean,
+        median,
+        volatility: var.sqrt(),
+        up,
+        best: returns[n - 1],
+        worst: returns[0],
+    })
+}
+
+/// Statistics of every node won by at least one day, sorted by node.
+///
+/// `days[i]` is the common day of the row won by `winners[i]`. Returns are those of
+/// instrument `i` from the close of the day to the close `horizon` common days later.
+pub fn node_stats(
+    market: &Market,
+    instrument: usize,
+    days: &[usize],
+    winners: &[Node],
+    horizons: &[usize],
+) -> Vec<NodeStats> {
+    let mut members = BTreeMap::new();
+    for (t, node) in days.iter().zip(winners.iter()) {
+        members.entry(*node).or_insert_with(Vec::new).push(*t);
+    }
+    members
+        .into_iter()
+        .map(|(node, ts)| {
+            let returns = horizons
+                .iter()
+                .filter_map(|h| {
+                    let returns = ts
+                        .iter()
+                        .filter(|t| *t + h < market.days.len())
+                        .map(|t| {
+                            let close = market.bar(instrument, *t).close;
+                            (
+                                market.day(*t),
+                                market.bar(instrument, t + h).close / close - 1.0,
+                            )
+                        })
+                        .collect();
+                    return_stats(*h, returns)
+                })
+                .collect();
+            NodeStats {
+                node,
+                days: ts.iter().map(|t| market.day(*t)).collect(),
+                returns,
+            }
+        })
+        .collect()
+}
+
+/// One line per node and horizon. The member days are joined by `;`.
+pub fn write_csv<W: Write>(w: W, stats: &[NodeStats]) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    w.write_record([
+        "row",
+        "col",
+        "days",
+        "horizon",
+        "n",
+        "mean",
+        "median",
+        "volatility",
+        "up",
+        "best_day",
+        "best",
+        "worst_day",
+        "worst",
+        "dates",
+    ])?;
+    for s in stats.iter() {
+        let dates = s
+            .days
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        for r in s.returns.iter() {
+            w.write_record(&[
+                s.node.0.to_string(),
+                s.node.1.to_string(),
+                s.days.len().to_string(),
+                r.horizon.to_string(),
+                r.n.to_string(),
+                r.mean.to_string(),
+                r.median.to_string(),
+                r.volatility.to_string(),
+                r.up.to_string(),
+                r.best.0.to_string(),
+                r.best.1.to_string(),
+                r.worst.0.to_string(),
+                r.worst.1.to_string(),
+                dates.clone(),
+            ])?;
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
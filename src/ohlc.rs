@@ -1,17 +1,54 @@
 use std::fmt;
 use std::fs::File;
+use std::io::Write;
 
 use crate::error_def::*;
 use chrono::NaiveDate;
 use error_chain::bail;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+/// Prices are kept as `f64` and written in their shortest exact representation,
+/// so a value read from a file is written back unchanged.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct OHLC {
-    pub open: f32,
-    pub high: f32,
-    pub low: f32,
-    pub close: f32,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Traded volume, if the source has one.
+    #[cfg_attr(
+        feature = "serialize",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub volume: Option<f64>,
 }
+
+/// A bar together with its instrument and day, e.g. one line of a JSON-lines export:
+/// `{"isin":"DE0008469008","day":"2019-11-08","open":13303.2,"high":13307.3,"low":13196.1,"close":13228.6}`,
+/// with `"volume"` if it is known.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Bar {
+    pub isin: String,
+    pub day: NaiveDate,
+    #[cfg_attr(feature = "serialize", serde(flatten))]
+    pub ohlc: OHLC,
+}
+
+impl From<(String, NaiveDate, OHLC)> for Bar {
+    fn from((isin, day, ohlc): (String, NaiveDate, OHLC)) -> Bar {
+        Bar { isin, day, ohlc }
+    }
+}
+
+impl From<Bar> for (String, NaiveDate, OHLC) {
+    fn from(bar: Bar) -> (String, NaiveDate, OHLC) {
+        (bar.isin, bar.day, bar.ohlc)
+    }
+}
+
 impl fmt::Display for OHLC {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -32,25 +69,32 @@ impl fmt::Debug for OHLC {
 }
 
 impl OHLC {
+    /// Lines of `date open high low close`, optionally followed by the volume.
     pub fn load_file(f: File) -> Result<Vec<(NaiveDate, OHLC)>> {
         let mut rdr = csv::ReaderBuilder::new()
             .delimiter(b' ')
             .has_headers(false)
+            .flexible(true)
             .from_reader(f);
 
         let mut ohlc_data = vec![];
         for result in rdr.records() {
             if let Ok(record) = result {
                 let day = NaiveDate::parse_from_str(&record[0], "%Y-%m-%d")?;
-                let open: f32 = record[1].parse()?;
-                let high: f32 = record[2].parse()?;
-                let low: f32 = record[3].parse()?;
-                let close: f32 = record[4].parse()?;
+                let open: f64 = record[1].parse()?;
+                let high: f64 = record[2].parse()?;
+                let low: f64 = record[3].parse()?;
+                let close: f64 = record[4].parse()?;
+                let volume = match record.get(5) {
+                    Some(v) if !v.is_empty() => Some(v.parse()?),
+                    _ => None,
+                };
                 let ohlc = OHLC {
                     open,
                     high,
                     low,
                     close,
+                    volume,
                 };
                 ohlc_data.push((day, ohlc));
             } else {
@@ -59,4 +103,69 @@ impl OHLC {
         }
         Ok(ohlc_data)
     }
+
+    pub fn save_file(mut f: File, ohlc_data: &[(NaiveDate, OHLC)]) -> Result<()> {
+        for (day, e) in ohlc_data.iter() {
+            match e.volume {
+                Some(volume) => writeln!(
+                    f,
+                    "{} {} {} {} {} {}",
+                    day, e.open, e.high, e.low, e.close, volume
+                )?,
+                None => writeln!(f, "{} {} {} {} {}", day, e.open, e.high, e.low, e.close)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load_are_bit_exact() {
+        let ohlc_data = vec![
+            (
+                NaiveDate::from_ymd(2019, 10, 30),
+                OHLC {
+                    open: 0.1 + 0.2,
+                    high: 1e-7,
+                    low: 1.0 / 3.0,
+                    close: 2f64.sqrt() * 1000.0,
+                    volume: None,
+                },
+            ),
+            (
+                NaiveDate::from_ymd(2019, 10, 31),
+                OHLC {
+                    open: 12866.789999999999,
+                    high: f64::MAX,
+                    low: f64::MIN_POSITIVE,
+                    close: 5e-324,
+                    volume: None,
+                },
+            ),
+        ];
+        let path = std::env::temp_dir().join(format!("ohlc-{}.csv", std::process::id()));
+        OHLC::save_file(File::create(&path).unwrap(), &ohlc_data).unwrap();
+        let loaded = OHLC::load_file(File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.len(), ohlc_data.len());
+        for ((day, a), (expected_day, b)) in loaded.iter().zip(ohlc_data.iter()) {
+            assert_eq!(day, expected_day);
+            for (x, y) in [
+                (a.open, b.open),
+                (a.high, b.high),
+                (a.low, b.low),
+                (a.close, b.close),
+            ]
+            .iter()
+            {
+                assert_eq!(x.to_bits(), y.to_bits(), "{} read back as {}", y, x);
+            }
+        }
+    }
 }
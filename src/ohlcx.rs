@@ -0,0 +1,63 @@
+use std::fmt;
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::ohlc::OHLC;
+
+/// A bar together with the close of the bar before.
+pub struct OHLCX {
+    pub ohlc: OHLC,
+    pub last_close: f64,
+}
+
+impl OHLCX {
+    /// The bar relative to the last close, scaled by 20 to bring daily moves near ±1.
+    pub fn as_f64_vec(&self) -> Vec<f64> {
+        let lc = self.last_close;
+        vec![
+            (self.ohlc.open / lc - 1.0) * 20.0,
+            (self.ohlc.high / lc - 1.0) * 20.0,
+            (self.ohlc.low / lc - 1.0) * 20.0,
+            (self.ohlc.close / lc - 1.0) * 20.0,
+        ]
+    }
+
+    /// Pair every bar with the close of its predecessor. The first bar is dropped.
+    pub fn from_history(ohlc_data: Vec<(NaiveDate, OHLC)>) -> Vec<(NaiveDate, OHLCX)> {
+        let mut opt_last_close = None;
+        let mut ohlc_x_data = vec![];
+        for (day, e) in ohlc_data.into_iter() {
+            let close = e.close;
+            if let Some(last_close) = opt_last_close {
+                ohlc_x_data.push((
+                    day,
+                    OHLCX {
+                        ohlc: e,
+                        last_close,
+                    },
+                ));
+            }
+            opt_last_close = Some(close);
+        }
+        ohlc_x_data
+    }
+}
+
+impl fmt::Debug for OHLCX {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OHLCX({}, last={})", self.ohlc, self.last_close)
+    }
+}
+
+/// One-hot encoding of the trading weekday. Weekends are all zero.
+pub fn weekday_f64_vec(day: &NaiveDate) -> Vec<f64> {
+    match day.weekday() {
+        chrono::Weekday::Mon => vec![1.0, 0.0, 0.0, 0.0, 0.0],
+        chrono::Weekday::Tue => vec![0.0, 1.0, 0.0, 0.0, 0.0],
+        chrono::Weekday::Wed => vec![0.0, 0.0, 1.0, 0.0, 0.0],
+        chrono::Weekday::Thu => vec![0.0, 0.0, 0.0, 1.0, 0.0],
+        chrono::Weekday::Fri => vec![0.0, 0.0, 0.0, 0.0, 1.0],
+        chrono::Weekday::Sat => vec![0.0, 0.0, 0.0, 0.0, 0.0],
+        chrono::Weekday::Sun => vec![0.0, 0.0, 0.0, 0.0, 0.0],
+    }
+}
@@ -0,0 +1,265 @@
+//! Risk figures of return series: volatility, beta, correlation, value at risk
+//! and drawdowns.
+//!
+//! Returns are simple daily returns. Value at risk and expected shortfall are
+//! losses, positive numbers as fraction of the value, at a confidence like 0.95.
+//!
+//! Figures of one instrument use all of its history; those comparing two
+//! instruments use the days both of them traded.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use chrono::NaiveDate;
+
+use crate::align::align;
+use crate::backtest::TRADING_DAYS;
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+
+/// Close-to-close returns of a history.
+pub fn returns(history: &[(NaiveDate, OHLC)]) -> Vec<f64> {
+    history
+        .windows(2)
+        .map(|w| w[1].1.close / w[0].1.close - 1.0)
+        .collect()
+}
+
+/// Returns of two histories over the days both of them traded.
+pub fn paired_returns(a: &[(NaiveDate, OHLC)], b: &[(NaiveDate, OHLC)]) -> (Vec<f64>, Vec<f64>) {
+    let closes = |h: &[(NaiveDate, OHLC)]| h.iter().map(|e| (e.0, e.1.close)).collect();
+    let common = align(vec![closes(a), closes(b)]);
+    common
+        .windows(2)
+        .map(|w| (w[1].1[0] / w[0].1[0] - 1.0, w[1].1[1] / w[0].1[1] - 1.0))
+        .unzip()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample covariance, `None` for less than two values.
+pub fn covariance(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return None;
+    }
+    let (a, b) = (&a[..n], &b[..n]);
+    let (ma, mb) = (mean(a), mean(b));
+    let sum = a
+        .iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - ma) * (y - mb))
+        .sum::<f64>();
+    Some(sum / (n - 1) as f64)
+}
+
+/// Sample standard deviation.
+pub fn volatility(returns: &[f64]) -> Option<f64> {
+    covariance(returns, returns).map(f64::sqrt)
+}
+
+/// Standard deviation of daily returns scaled to a year.
+pub fn annualised_volatility(returns: &[f64]) -> Option<f64> {
+    volatility(returns).map(|v| v * TRADING_DAYS.sqrt())
+}
+
+/// Annualised volatility of the last `n` returns, for every return.
+pub fn rolling_volatility(returns: &[f64], n: usize) -> Vec<Option<f64>> {
+    (0..returns.len())
+        .map(|i| {
+            if n < 2 || i + 1 < n {
+                None
+            } else {
+                annualised_volatility(&returns[i + 1 - n..=i])
+            }
+        })
+        .collect()
+}
+
+/// Sensitivity of the returns to those of a benchmark over the same days.
+pub fn beta(returns: &[f64], benchmark: &[f64]) -> Option<f64> {
+    let var = covariance(benchmark, benchmark)?;
+    if var > 0.0 {
+        Some(covariance(returns, benchmark)? / var)
+    } else {
+        None
+    }
+}
+
+/// Pearson correlation of two return series over the same days.
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let sd = volatility(a)? * volatility(b)?;
+    if sd > 0.0 {
+        Some(covariance(a, b)? / sd)
+    } else {
+        None
+    }
+}
+
+/// Correlation of the returns of every pair of histories over the days both
+/// traded, NaN where undefined.
+pub fn correlation_matrix(histories: &[Vec<(NaiveDate, OHLC)>]) -> Vec<Vec<f64>> {
+    histories
+        .iter()
+        .map(|a| {
+            histories
+                .iter()
+                .map(|b| {
+                    let (ra, rb) = paired_returns(a, b);
+                    correlation(&ra, &rb).unwrap_or(f64::NAN)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Value at risk and expected shortfall.
+#[derive(Clone, Copy, Debug)]
+pub struct ValueAtRisk {
+    pub confidence: f64,
+    /// Loss not exceeded with the given confidence.
+    pub var: f64,
+    /// Mean loss beyond the value at risk, also known as CVaR.
+    pub expected_shortfall: f64,
+}
+
+/// From the empirical distribution of the returns.
+pub fn historical_var(returns: &[f64], confidence: f64) -> Option<ValueAtRisk> {
+    if returns.is_empty() {
+        return None;
+    }
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let tail = (((1.0 - confidence) * sorted.len() as f64).ceil() as usize).max(1);
+    Some(ValueAtRisk {
+        confidence,
+        var: -sorted[tail - 1],
+        expected_shortfall: -mean(&sorted[..tail]),
+    })
+}
+
+/// Assuming normally distributed returns with the sample mean and standard deviation.
+pub fn parametric_var(returns: &[f64], confidence: f64) -> Option<ValueAtRisk> {
+    let sd = volatility(returns)?;
+    let m = mean(returns);
+    let z = normal_quantile(confidence);
+    let density = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    Some(ValueAtRisk {
+        confidence,
+        var: z * sd - m,
+        expected_shortfall: sd * density / (1.0 - confidence) - m,
+    })
+}
+
+/// Inverse of the standard normal distribution function, after Acklam.
+/// The relative error is below 1.2e-9.
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// Fall of every value below the highest value so far, as a fraction of that high.
+pub fn drawdowns(values: &[f64]) -> Vec<f64> {
+    let mut peak = f64::NEG_INFINITY;
+    values
+        .iter()
+        .map(|v| {
+            peak = peak.max(*v);
+            if peak > 0.0 {
+                (peak - v) / peak
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// `day` followed by the rolling volatility over `window` returns and the
+/// drawdown of the close of every instrument, on every day any of them traded.
+/// The cells of an instrument are empty on days it did not trade.
+pub fn write_series<W: Write>(
+    w: W,
+    isins: &[String],
+    histories: &[Vec<(NaiveDate, OHLC)>],
+    window: usize,
+) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    let mut header = vec!["day".to_string()];
+    for isin in isins.iter() {
+        header.push(format!("{}.volatility", isin));
+        header.push(format!("{}.drawdown", isin));
+    }
+    w.write_record(&header)?;
+    let mut rows = BTreeMap::new();
+    for (i, history) in histories.iter().enumerate() {
+        let closes = history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+        // The first day has no return.
+        let mut volatility = vec![None];
+        volatility.extend(rolling_volatility(&returns(history), window));
+        for (t, drawdown) in drawdowns(&closes).into_iter().enumerate() {
+            let row = rows
+                .entry(history[t].0)
+                .or_insert_with(|| vec![String::new(); 2 * histories.len()]);
+            row[2 * i] = volatility[t].map(|v| v.to_string()).unwrap_or_default();
+            row[2 * i + 1] = drawdown.to_string();
+        }
+    }
+    for (day, row) in rows.into_iter() {
+        let mut record = vec![day.to_string()];
+        record.extend(row);
+        w.write_record(&record)?;
+    }
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,693 @@
+//! Filter and rank instruments by expressions over fields computed from their
+//! histories, each at its own last day.
+//!
+//! ```text
+//! close > sma(200) and rsi(14) < 30
+//! change(21) <= -10 or percentile(volatility(21)) >= 90
+//! ```
+//!
+//! Fields are `open`, `high`, `low`, `close` of the last bar, `sma(N)`, `ema(N)`,
+//! `rsi(N)`, `high(N)` and `low(N)` as the highest and lowest close of the last N
+//! days, `change(N)` as percent change of the close over N days and `volatility(N)`
+//! as annualised standard deviation of the last N daily returns in percent.
+//! `percentile(EXPR)` is the share of instruments in percent with a value not
+//! above that of the instrument. Expressions combine with `+ - * /`, the
+//! comparisons `< <= > >= == !=` and `and`, `or`, `not`. Comparisons are 1 when
+//! true and 0 when false; a field without enough history has no value and fails
+//! every comparison.
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use error_chain::bail;
+
+use crate::error_def::*;
+use crate::indicators;
+use crate::ohlc::OHLC;
+use crate::risk;
+
+/// Largest number of days a field may look back, far beyond any history.
+pub const MAX_DAYS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Open,
+    High,
+    Low,
+    Close,
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Highest(usize),
+    Lowest(usize),
+    Change(usize),
+    Volatility(usize),
+}
+
+impl Field {
+    /// The value at the last day of the history, oldest first.
+    pub fn value(&self, history: &[(NaiveDate, OHLC)]) -> Option<f64> {
+        let (_, last) = history.last()?;
+        let closes = || history.iter().map(|e| e.1.close).collect::<Vec<_>>();
+        let tail = |n: usize| {
+            if n > 0 && history.len() >= n {
+                Some(&history[history.len() - n..])
+            } else {
+                None
+            }
+        };
+        match *self {
+            Field::Open => Some(last.open),
+            Field::High => Some(last.high),
+            Field::Low => Some(last.low),
+            Field::Close => Some(last.close),
+            Field::Sma(n) => *indicators::sma(&closes(), n).last()?,
+            Field::Ema(n) => *indicators::ema(&closes(), n).last()?,
+            Field::Rsi(n) => *indicators::rsi(&closes(), n).last()?,
+            Field::Highest(n) => tail(n).map(|t| {
+                t.iter()
+                    .map(|e| e.1.close)
+                    .fold(f64::NEG_INFINITY, f64::max)
+            }),
+            Field::Lowest(n) => {
+                tail(n).map(|t| t.iter().map(|e| e.1.close).fold(f64::INFINITY, f64::min))
+            }
+            Field::Change(n) => {
+                tail(n.checked_add(1)?).map(|t| (last.close / t[0].1.close - 1.0) * 100.0)
+            }
+            Field::Volatility(n) => {
+                let t = tail(n.checked_add(1)?)?;
+                let returns = t
+                    .windows(2)
+                    .map(|w| w[1].1.close / w[0].1.close - 1.0)
+                    .collect::<Vec<_>>();
+                risk::annualised_volatility(&returns).map(|v| v * 100.0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Open => write!(f, "open"),
+            Field::High => write!(f, "high"),
+            Field::Low => write!(f, "low"),
+            Field::Close => write!(f, "close"),
+            Field::Sma(n) => write!(f, "sma({})", n),
+            Field::Ema(n) => write!(f, "ema({})", n),
+            Field::Rsi(n) => write!(f, "rsi({})", n),
+            Field::Highest(n) => write!(f, "high({})", n),
+            Field::Lowest(n) => write!(f, "low({})", n),
+            Field::Change(n) => write!(f, "change({})", n),
+            Field::Volatility(n) => write!(f, "volatility({})", n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::And => "and",
+            Op::Or => "or",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Field(Field),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Percentile(Box<Expr>),
+}
+
+fn truth(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Expr {
+    /// The value for every instrument, given by its history.
+    pub fn evaluate(&self, histories: &[&[(NaiveDate, OHLC)]]) -> Vec<Option<f64>> {
+        match self {
+            Expr::Number(x) => vec![Some(*x); histories.len()],
+            Expr::Field(field) => histories.iter().map(|h| field.value(h)).collect(),
+            Expr::Neg(e) => e
+                .evaluate(histories)
+                .into_iter()
+                .map(|v| v.map(|v| -v))
+                .collect(),
+            Expr::Not(e) => e
+                .evaluate(histories)
+                .into_iter()
+                .map(|v| v.map(|v| truth(v == 0.0)))
+                .collect(),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate(histories), b.evaluate(histories));
+                a.into_iter()
+                    .zip(b)
+                    .map(|(a, b)| match (op, a, b) {
+                        // Missing data only decides a logical operation if the other side does not.
+                        (Op::And, Some(x), _) | (Op::And, _, Some(x)) if x == 0.0 => Some(0.0),
+                        (Op::Or, Some(x), _) | (Op::Or, _, Some(x)) if x != 0.0 => Some(1.0),
+                        (_, Some(a), Some(b)) => Some(match op {
+                            Op::Add => a + b,
+                            Op::Sub => a - b,
+                            Op::Mul => a * b,
+                            Op::Div => a / b,
+                            Op::Lt => truth(a < b),
+                            Op::Le => truth(a <= b),
+                            Op::Gt => truth(a > b),
+                            Op::Ge => truth(a >= b),
+                            Op::Eq => truth(a == b),
+                            Op::Ne => truth(a != b),
+                            Op::And => truth(a != 0.0 && b != 0.0),
+                            Op::Or => truth(a != 0.0 || b != 0.0),
+                        }),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            Expr::Percentile(e) => {
+                let values = e.evaluate(histories);
+                let known = values.iter().filter_map(|v| *v).collect::<Vec<_>>();
+                values
+                    .iter()
+                    .map(|v| {
+                        v.map(|v| {
+                            known.iter().filter(|x| **x <= v).count() as f64 * 100.0
+                                / known.len() as f64
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The fields used, in order of appearance without repetitions.
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields = vec![];
+        self.collect_fields(&mut fields);
+        fields
+    }
+
+    fn collect_fields(&self, fields: &mut Vec<Field>) {
+        match self {
+            Expr::Number(_) => (),
+            Expr::Field(field) => {
+                if !fields.contains(field) {
+                    fields.push(*field);
+                }
+            }
+            Expr::Neg(e) | Expr::Not(e) | Expr::Percentile(e) => e.collect_fields(fields),
+            Expr::Binary(_, a, b) => {
+                a.collect_fields(fields);
+                b.collect_fields(fields);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Nested operations are put in parentheses, so the text parses to the same expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |e: &Expr| match e {
+            Expr::Binary(..) | Expr::Not(_) => format!("({})", e),
+            _ => e.to_string(),
+        };
+        match self {
+            Expr::Number(x) => write!(f, "{}", x),
+            Expr::Field(field) => write!(f, "{}", field),
+            Expr::Neg(e) => write!(f, "-{}", operand(e)),
+            Expr::Not(e) => write!(f, "not {}", operand(e)),
+            Expr::Binary(op, a, b) => write!(f, "{} {} {}", operand(a), op, operand(b)),
+            Expr::Percentile(e) => write!(f, "percentile({})", e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    const SYMBOLS: [&str; 15] = [
+        "<=", ">=", "==", "!=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "(", ")",
+    ];
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            match rest[..len].parse() {
+                Ok(x) => tokens.push(Token::Number(x)),
+                Err(_) => bail!("Invalid number in expression: {}", &rest[..len]),
+            }
+            len
+        } else if c.is_ascii_alphabetic() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_lowercase()));
+            len
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(symbol) => {
+                    tokens.push(Token::Symbol(symbol));
+                    symbol.len()
+                }
+                None => bail!("Unexpected '{}' in expression", c),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, lowest precedence first.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn accept(&mut self, alternatives: &[&str]) -> Option<String> {
+        let found = match self.peek() {
+            Some(Token::Symbol(s)) if alternatives.contains(s) => s.to_string(),
+            Some(Token::Name(s)) if alternatives.contains(&s.as_str()) => s.clone(),
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(found)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        match self.accept(&[symbol]) {
+            Some(_) => Ok(()),
+            None => bail!("Expected '{}' in expression", symbol),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut e = self.and()?;
+        while self.accept(&["or", "||"]).is_some() {
+            e = Expr::Binary(Op::Or, Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut e = self.not()?;
+        while self.accept(&["and", "&&"]).is_some() {
+            e = Expr::Binary(Op::And, Box::new(e), Box::new(self.not()?));
+        }
+        Ok(e)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.accept(&["not", "!"]).is_some() {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let e = self.sum()?;
+        let op = match self.accept(&["<", "<=", ">", ">=", "==", "!="]) {
+            Some(op) => op,
+            None => return Ok(e),
+        };
+        let op = match op.as_str() {
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "==" => Op::Eq,
+            _ => Op::Ne,
+        };
+        Ok(Expr::Binary(op, Box::new(e), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let mut e = self.product()?;
+        while let Some(op) = self.accept(&["+", "-"]) {
+            let op = if op == "+" { Op::Add } else { Op::Sub };
+            e = Expr::Binary(op, Box::new(e), Box::new(self.product()?));
+        }
+        Ok(e)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let mut e = self.unary()?;
+        while let Some(op) = self.accept(&["*", "/"]) {
+            let op = if op == "*" { Op::Mul } else { Op::Div };
+            e = Expr::Binary(op, Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.accept(&["-"]).is_some() {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        let name = match token {
+            Some(Token::Number(x)) => return Ok(Expr::Number(x)),
+            Some(Token::Symbol("(")) => {
+                let e = self.or()?;
+                self.expect(")")?;
+                return Ok(e);
+            }
+            Some(Token::Name(name)) => name,
+            Some(Token::Symbol(s)) => bail!("Unexpected '{}' in expression", s),
+            None => bail!("Unexpected end of expression"),
+        };
+        if self.accept(&["("]).is_none() {
+            return match name.as_str() {
+                "open" => Ok(Expr::Field(Field::Open)),
+                "high" => Ok(Expr::Field(Field::High)),
+                "low" => Ok(Expr::Field(Field::Low)),
+                "close" => Ok(Expr::Field(Field::Close)),
+                _ => bail!("Unknown field: {}", name),
+            };
+        }
+        if name == "percentile" {
+            let e = self.or()?;
+            self.expect(")")?;
+            return Ok(Expr::Percentile(Box::new(e)));
+        }
+        let n = match self.peek() {
+            Some(Token::Number(x)) if x.fract() == 0.0 && *x >= 1.0 && *x <= MAX_DAYS as f64 => {
+                *x as usize
+            }
+            _ => bail!("{} needs a whole number of days up to {}", name, MAX_DAYS),
+        };
+        self.pos += 1;
+        self.expect(")")?;
+        let field = match name.as_str() {
+            "sma" => Field::Sma(n),
+            "ema" => Field::Ema(n),
+            "rsi" => Field::Rsi(n),
+            "high" => Field::Highest(n),
+            "low" => Field::Lowest(n),
+            "change" => Field::Change(n),
+            "volatility" => Field::Volatility(n),
+            _ => bail!("Unknown function: {}", name),
+        };
+        Ok(Expr::Field(field))
+    }
+}
+
+impl FromStr for Expr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Expr> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let e = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            bail!("Unexpected {:?} in expression", parser.tokens[parser.pos]);
+        }
+        Ok(e)
+    }
+}
+
+/// An instrument that passed the filter, with the values of the columns.
+#[derive(Clone, Debug)]
+pub struct ScreenRow {
+    pub isin: String,
+    pub day: NaiveDate,
+    pub values: Vec<Option<f64>>,
+}
+
+/// The instruments with a non-zero value of `filter`, all without one, in the
+/// given order. Instruments without history are left out.
+pub fn screen(
+    isins: &[String],
+    histories: &[Vec<(NaiveDate, OHLC)>],
+    filter: Option<&Expr>,
+    columns: &[Expr],
+) -> Vec<ScreenRow> {
+    let (isins, histories): (Vec<_>, Vec<_>) = isins
+        .iter()
+        .zip(histories.iter())
+        .filter(|(_, h)| !h.is_empty())
+        .map(|(isin, h)| (isin, h.as_slice()))
+        .unzip();
+    let passed = match filter {
+        Some(filter) => filter
+            .evaluate(&histories)
+            .into_iter()
+            .map(|v| v.map(|v| v != 0.0).unwrap_or(false))
+            .collect(),
+        None => vec![true; histories.len()],
+    };
+    let values = columns
+        .iter()
+        .map(|c| c.evaluate(&histories))
+        .collect::<Vec<_>>();
+    (0..histories.len())
+        .filter(|i| passed[*i])
+        .map(|i| ScreenRow {
+            isin: isins[i].clone(),
+            day: histories[i].last().unwrap().0,
+            values: values.iter().map(|v| v[i]).collect(),
+        })
+        .collect()
+}
+
+/// Sorts by the values of one column, rows without a value last.
+pub fn sort_rows(rows: &mut [ScreenRow], column: usize, descending: bool) {
+    rows.sort_by(|a, b| match (a.values[column], b.values[column]) {
+        (Some(x), Some(y)) => {
+            let order = x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal);
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        }
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+/// `isin,day` and one column per expression, empty without a value.
+pub fn write_csv<W: Write>(w: W, columns: &[Expr], rows: &[ScreenRow]) -> Result<()> {
+    let mut w = csv::Writer::from_writer(w);
+    let mut header = vec!["isin".to_string(), "day".to_string()];
+    header.extend(columns.iter().map(|c| c.to_string()));
+    w.write_record(&header)?;
+    for row in rows.iter() {
+        let mut record = vec![row.isin.clone(), row.day.to_string()];
+        record.extend(
+            row.values
+                .iter()
+                .map(|v| v.map(|v| v.to_string()).unwrap_or_default()),
+        );
+        w.write_record(&record)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Expr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("not open and close"), parse("(not open) and close"));
+        assert_eq!(parse("open + high * low"), parse("open + (high * low)"));
+        assert_eq!(parse("open - high - low"), parse("(open - high) - low"));
+        assert_eq!(
+            parse("open < 1 or high > 2 and low == 3"),
+            parse("(open < 1) or ((high > 2) and (low == 3))")
+        );
+    }
+
+    #[test]
+    fn chained_comparison_fails() {
+        assert!("open < high < low".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn incomplete_expressions_fail() {
+        for s in &[
+            "open and", "or close", "open +", "sma(", "sma(0)", "rsi(2.5)", "foo",
+        ] {
+            assert!(s.parse::<Expr>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn huge_day_counts_fail() {
+        assert!("change(100000000000000000000) > 0".parse::<Expr>().is_err());
+        assert!("volatility(100000)".parse::<Expr>().is_ok());
+    }
+
+    #[test]
+    fn logic_with_one_side_missing() {
+        let day = NaiveDate::from_ymd(2019, 1, 2);
+        let bar = OHLC {
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: None,
+        };
+        let short = [(day, bar)];
+        let histories = [&short[..]];
+        let value = |s: &str| parse(s).evaluate(&histories)[0];
+        assert_eq!(value("sma(5) > 0"), None);
+        assert_eq!(value("sma(5) > 0 and close > 2"), Some(0.0));
+        assert_eq!(value("close > 2 and sma(5) > 0"), Some(0.0));
+        assert_eq!(value("sma(5) > 0 and close > 0"), None);
+        assert_eq!(value("sma(5) > 0 or close > 0"), Some(1.0));
+        assert_eq!(value("close > 0 or sma(5) > 0"), Some(1.0));
+        assert_eq!(value("sma(5) > 0 or close > 2"), None);
+    }
+
+    #[test]
+    fn percentile_ranks() {
+        let (day, next) = (
+            NaiveDate::from_ymd(2019, 1, 2),
+            NaiveDate::from_ymd(2019, 1, 3),
+        );
+        let a = [(
+            day,
+            OHLC {
+                open: 3.0,
+                high: 3.0,
+                low: 3.0,
+                close: 3.0,
+                volume: None,
+            },
+        )];
+        let b = [(
+            day,
+            OHLC {
+                open: 1.0,
+                high: 1.0,
+                low: 1.0,
+                close: 1.0,
+                volume: None,
+            },
+        )];
+        let c = [
+            (
+                day,
+                OHLC {
+                    open: 2.0,
+                    high: 2.0,
+                    low: 2.0,
+                    close: 2.0,
+                    volume: None,
+                },
+            ),
+            (
+                next,
+                OHLC {
+                    open: 2.0,
+                    high: 2.0,
+                    low: 2.0,
+                    close: 2.0,
+                    volume: None,
+                },
+            ),
+        ];
+        let d = [(
+            day,
+            OHLC {
+                open: 2.0,
+                high: 2.0,
+                low: 2.0,
+                close: 2.0,
+                volume: None,
+            },
+        )];
+        let histories = [&a[..], &b[..], &c[..], &d[..]];
+        assert_eq!(
+            parse("percentile(close)").evaluate(&histories),
+            vec![Some(100.0), Some(25.0), Some(75.0), Some(75.0)]
+        );
+        // Instruments without a value are neither ranked nor counted.
+        assert_eq!(
+            parse("percentile(change(1))").evaluate(&histories),
+            vec![None, None, Some(100.0), None]
+        );
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for s in &[
+            "close > sma(200) and rsi(14) < 30",
+            "change(21) <= -10 or percentile(volatility(21)) >= 90",
+            "not not (open - (high - low)) != -(close / 2.5)",
+            "-(open + 1) * (high(5) - low(5)) == ema(3)",
+            "!(open < 1 || high >= 2) && (low == 3)",
+        ] {
+            let e = parse(s);
+            assert_eq!(parse(&e.to_string()), e, "{} shown as {}", s, e);
+        }
+    }
+}
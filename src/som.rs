@@ -0,0 +1,157 @@
+use ndarray::{s, Array1, Array2, Array3, ArrayView1};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// A random number generator for training, reproducible for the same seed.
+pub fn seeded_rng(seed: u64) -> StdRng {
+    let mut bytes = [0u8; 32];
+    for (i, b) in seed.to_le_bytes().iter().enumerate() {
+        bytes[i] = *b;
+    }
+    StdRng::from_seed(bytes)
+}
+
+/// A rectangular self-organising map with gaussian neighbourhood.
+///
+/// Learning rate and neighbourhood radius decay as `x / (1 + t / (T / 2))`,
+/// like the defaults of `rusticsom` this replaces.
+pub struct Som {
+    weights: Array3<f64>,
+    pub learning_rate: f64,
+    pub sigma: f64,
+}
+
+impl Som {
+    /// A map of `rows` x `cols` nodes with weights drawn uniformly from 0..1.
+    pub fn new(rows: usize, cols: usize, inputs: usize, rng: &mut StdRng) -> Som {
+        let mut weights = Array3::<f64>::zeros((rows, cols, inputs));
+        for w in weights.iter_mut() {
+            *w = rng.gen::<f64>();
+        }
+        Som::from_weights(weights)
+    }
+
+    pub fn from_weights(weights: Array3<f64>) -> Som {
+        Som {
+            weights,
+            learning_rate: 0.5,
+            sigma: 1.0,
+        }
+    }
+
+    /// `(rows, cols, inputs)`
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.weights.dim()
+    }
+
+    pub fn weights(&self) -> &Array3<f64> {
+        &self.weights
+    }
+
+    pub fn node_weights(&self, node: (usize, usize)) -> ArrayView1<'_, f64> {
+        self.weights.slice(s![node.0, node.1, ..])
+    }
+
+    fn distance2(&self, node: (usize, usize), v: &ArrayView1<f64>) -> f64 {
+        self.node_weights(node)
+            .iter()
+            .zip(v.iter())
+            .map(|(w, x)| (w - x) * (w - x))
+            .sum()
+    }
+
+    /// The node closest to `v`.
+    pub fn winner(&self, v: ArrayView1<f64>) -> (usize, usize) {
+        let (rows, cols, _) = self.dim();
+        let mut best = (0, 0);
+        let mut best_d = 1. / 0.;
+        for r in 0..rows {
+            for c in 0..cols {
+                let d = self.distance2((r, c), &v);
+                if d < best_d {
+                    best_d = d;
+                    best = (r, c);
+                }
+            }
+        }
+        best
+    }
+
+    fn update(&mut self, v: ArrayView1<f64>, winner: (usize, usize), t: u32, iterations: u32) {
+        let decay = 1.0 + t as f64 / (iterations as f64 / 2.0);
+        let lr = self.learning_rate / decay;
+        let sigma = self.sigma / decay;
+        let (rows, cols, inputs) = self.dim();
+        for r in 0..rows {
+            for c in 0..cols {
+                let dr = r as f64 - winner.0 as f64;
+                let dc = c as f64 - winner.1 as f64;
+                let h = (-(dr * dr + dc * dc) / (2.0 * sigma * sigma)).exp();
+                for k in 0..inputs {
+                    let w = self.weights[[r, c, k]];
+                    self.weights[[r, c, k]] = w + lr * h * (v[k] - w);
+                }
+            }
+        }
+    }
+
+    /// Train with `iterations` rows drawn at random from `data`.
+    pub fn train_random(&mut self, data: &Array2<f64>, iterations: u32, rng: &mut StdRng) {
+        let n = data.rows();
+        for t in 0..iterations {
+            let v = data.row(rng.gen_range(0, n));
+            let winner = self.winner(v);
+            self.update(v, winner, t, iterations);
+        }
+    }
+
+    /// Number of rows of `data` won by each node.
+    pub fn activation_response(&self, data: &Array2<f64>) -> Array2<usize> {
+        let (rows, cols, _) = self.dim();
+        let mut hits = Array2::<usize>::zeros((rows, cols));
+        for v in data.outer_iter() {
+            hits[self.winner(v)] += 1;
+        }
+        hits
+    }
+
+    /// Winners of all rows of `data`.
+    pub fn winners(&self, data: &Array2<f64>) -> Vec<(usize, usize)> {
+        data.outer_iter().map(|v| self.winner(v)).collect()
+    }
+
+    /// U-matrix: for every node the mean distance of its weights to those of
+    /// its direct neighbours. High values mark borders between clusters.
+    pub fn distance_map(&self) -> Array2<f64> {
+        let (rows, cols, _) = self.dim();
+        let mut map = Array2::<f64>::zeros((rows, cols));
+        for r in 0..rows {
+            for c in 0..cols {
+                let v = self.node_weights((r, c));
+                let mut sum = 0.0;
+                let mut n = 0;
+                let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+                for (dr, dc) in neighbours.iter() {
+                    let nr = r as isize + dr;
+                    let nc = c as isize + dc;
+                    if nr >= 0 && nc >= 0 && (nr as usize) < rows && (nc as usize) < cols {
+                        sum += self.distance2((nr as usize, nc as usize), &v).sqrt();
+                        n += 1;
+                    }
+                }
+                map[[r, c]] = if n > 0 { sum / n as f64 } else { 0.0 };
+            }
+        }
+        map
+    }
+
+    /// The weight of input `k` of every node.
+    pub fn component_plane(&self, k: usize) -> Array2<f64> {
+        self.weights.slice(s![.., .., k]).to_owned()
+    }
+
+    /// The weights of a node as an owned vector.
+    pub fn prototype(&self, node: (usize, usize)) -> Array1<f64> {
+        self.node_weights(node).to_owned()
+    }
+}
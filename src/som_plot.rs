@@ -0,0 +1,143 @@
+//! Images of what a trained map has learned: U-matrix, hit map, component planes
+//! and the transition graph of the winners. Node (0, 0) is drawn top left.
+
+use std::path::Path;
+
+use ndarray::Array2;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::chart::plot_err;
+use crate::error_def::*;
+use crate::markov::{Node, Transitions};
+use crate::som::Som;
+
+const SIZE: (u32, u32) = (800, 800);
+
+/// Blue for the lowest, red for the highest value of `min..max`.
+fn heat(v: f64, min: f64, max: f64) -> HSLColor {
+    let x = if max > min {
+        (v - min) / (max - min)
+    } else {
+        0.5
+    };
+    HSLColor((1.0 - x) * 2.0 / 3.0, 1.0, 0.5)
+}
+
+fn centre(node: Node, rows: usize) -> (f64, f64) {
+    (node.1 as f64 + 0.5, (rows - 1 - node.0) as f64 + 0.5)
+}
+
+/// Draw one value per node as coloured cells.
+fn draw_heatmap<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    caption: &str,
+    values: &Array2<f64>,
+) -> Result<()> {
+    let (rows, cols) = values.dim();
+    let min = values.iter().cloned().fold(1. / 0., f64::min);
+    let max = values.iter().cloned().fold(-1. / 0., f64::max);
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("Arial", 20.0).into_font())
+        .margin(5)
+        .build_ranged(0.0..cols as f64, 0.0..rows as f64)
+        .map_err(plot_err)?;
+    chart
+        .draw_series(values.indexed_iter().map(|((r, c), v)| {
+            let (x, y) = centre((r, c), rows);
+            Rectangle::new(
+                [(x - 0.5, y - 0.5), (x + 0.5, y + 0.5)],
+                heat(*v, min, max).filled(),
+            )
+        }))
+        .map_err(plot_err)?;
+    Ok(())
+}
+
+fn plot_heatmap(path: &Path, caption: &str, values: &Array2<f64>) -> Result<()> {
+    let root = BitMapBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+    draw_heatmap(&root, caption, values)
+}
+
+/// Mean distance of every node to its neighbours, borders between clusters are red.
+pub fn plot_u_matrix(som: &Som, path: &Path) -> Result<()> {
+    plot_heatmap(path, "U-matrix", &som.distance_map())
+}
+
+/// Number of rows of `data` won by every node.
+pub fn plot_hits(som: &Som, data: &Array2<f64>, path: &Path) -> Result<()> {
+    let hits = som.activation_response(data).mapv(|n| n as f64);
+    plot_heatmap(path, "Hits", &hits)
+}
+
+/// One panel per input with the weight of that input at every node.
+pub fn plot_component_planes(som: &Som, names: &[String], path: &Path) -> Result<()> {
+    let (_, _, inputs) = som.dim();
+    let per_row = (inputs as f64).sqrt().ceil().max(1.0) as usize;
+    let panel_rows = inputs.div_ceil(per_row);
+    let root = BitMapBackend::new(path, (300 * per_row as u32, 300 * panel_rows as u32))
+        .into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+    for (k, area) in root
+        .split_evenly((panel_rows, per_row))
+        .iter()
+        .enumerate()
+        .take(inputs)
+    {
+        let name = names.get(k).cloned().unwrap_or_else(|| k.to_string());
+        draw_heatmap(area, &name, &som.component_plane(k))?;
+    }
+    Ok(())
+}
+
+/// First order transitions between the nodes: a line per pair of nodes, darker for
+/// more frequent transitions, over the nodes drawn with a size by hits.
+/// Staying on the same node is not drawn.
+pub fn plot_transitions(som: &Som, winners: &[Node], path: &Path) -> Result<()> {
+    let (rows, cols, _) = som.dim();
+    let transitions = Transitions::from_sequence(winners, 1);
+    let mut edges = vec![];
+    for (context, successors) in transitions.iter() {
+        for (to, cnt) in successors.iter() {
+            if context[0] != *to {
+                edges.push((context[0], *to, *cnt));
+            }
+        }
+    }
+    edges.sort_by_key(|e| e.2);
+    let max_cnt = edges.iter().map(|e| e.2).max().unwrap_or(1) as f64;
+    let mut hits = Array2::<usize>::zeros((rows, cols));
+    for w in winners.iter() {
+        hits[*w] += 1;
+    }
+    let max_hits = hits.iter().cloned().max().unwrap_or(1).max(1) as f64;
+
+    let root = BitMapBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Transitions", ("Arial", 20.0).into_font())
+        .margin(5)
+        .build_ranged(0.0..cols as f64, 0.0..rows as f64)
+        .map_err(plot_err)?;
+    chart
+        .draw_series(edges.iter().map(|(from, to, cnt)| {
+            let alpha = 0.1 + 0.9 * *cnt as f64 / max_cnt;
+            PathElement::new(
+                vec![centre(*from, rows), centre(*to, rows)],
+                BLUE.mix(alpha).stroke_width(1 + (3.0 * alpha) as u32),
+            )
+        }))
+        .map_err(plot_err)?;
+    chart
+        .draw_series(
+            hits.indexed_iter()
+                .filter(|(_, n)| **n > 0)
+                .map(|(node, n)| {
+                    let size = 2 + (8.0 * *n as f64 / max_hits) as u32;
+                    Circle::new(centre(node, rows), size, RED.filled())
+                }),
+        )
+        .map_err(plot_err)?;
+    Ok(())
+}
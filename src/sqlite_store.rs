@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, NO_PARAMS};
+
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+use crate::store::{check_conflicts, ConflictRule, FetchLog, InstrumentMeta, Store};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const FETCH_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// All instruments in one SQLite database.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open the database, creating the tables if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStore> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS bars (
+                isin TEXT NOT NULL,
+                day TEXT NOT NULL,
+                open REAL NOT NULL,
+                high REAL NOT NULL,
+                low REAL NOT NULL,
+                close REAL NOT NULL,
+                volume REAL,
+                PRIMARY KEY (isin, day)
+            );
+            CREATE TABLE IF NOT EXISTS meta (
+                isin TEXT PRIMARY KEY,
+                name TEXT,
+                currency TEXT
+            );
+            CREATE TABLE IF NOT EXISTS fetch_log (
+                isin TEXT NOT NULL,
+                fetched TEXT NOT NULL,
+                url TEXT NOT NULL,
+                bars INTEGER NOT NULL
+            );",
+        )?;
+        // Databases created before the volume was stored lack its column.
+        let columns = conn
+            .prepare("PRAGMA table_info(bars)")?
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(1))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if !columns.iter().any(|c| c == "volume") {
+            conn.execute_batch("ALTER TABLE bars ADD COLUMN volume REAL")?;
+        }
+        Ok(SqliteStore { conn })
+    }
+
+    /// `conflict` is the SQLite conflict clause for days already stored, `REPLACE` or `IGNORE`.
+    fn write_bars(
+        &self,
+        isin: &str,
+        ohlc_data: &[(NaiveDate, OHLC)],
+        conflict: &str,
+    ) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!(
+            "INSERT OR {} INTO bars (isin, day, open, high, low, close, volume)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            conflict
+        ))?;
+        for (day, e) in ohlc_data.iter() {
+            stmt.execute(params![
+                isin,
+                day.format(DATE_FORMAT).to_string(),
+                e.open,
+                e.high,
+                e.low,
+                e.close,
+                e.volume
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Run `f` inside a transaction, which is rolled back if `f` fails.
+    fn in_transaction<T, F: FnOnce() -> Result<T>>(&self, f: F) -> Result<T> {
+        self.conn.execute_batch("BEGIN")?;
+        match f() {
+            Ok(t) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(t)
+            }
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Store for SqliteStore {
+    fn isins(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT isin FROM bars UNION SELECT isin FROM meta UNION SELECT isin FROM fetch_log
+             ORDER BY isin",
+        )?;
+        let isins = stmt
+            .query_map(NO_PARAMS, |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(isins)
+    }
+
+    fn load(&self, isin: &str) -> Result<Vec<(NaiveDate, OHLC)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT day, open, high, low, close, volume FROM bars WHERE isin = ?1 ORDER BY day",
+        )?;
+        let rows = stmt
+            .query_map(params![isin], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, Option<f64>>(5)?,
+                ))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut ohlc_data = vec![];
+        for (day, open, high, low, close, volume) in rows.into_iter() {
+            let day = NaiveDate::parse_from_str(&day, DATE_FORMAT)?;
+            let ohlc = OHLC {
+                open,
+                high,
+                low,
+                close,
+                volume,
+            };
+            ohlc_data.push((day, ohlc));
+        }
+        Ok(ohlc_data)
+    }
+
+    fn save(&self, isin: &str, ohlc_data: &[(NaiveDate, OHLC)]) -> Result<()> {
+        self.in_transaction(|| {
+            self.conn
+                .execute("DELETE FROM bars WHERE isin = ?1", params![isin])?;
+            self.write_bars(isin, ohlc_data, "REPLACE")
+        })
+    }
+
+    /// Only the new bars are written, the other stored ones stay untouched.
+    fn merge_with(
+        &self,
+        isin: &str,
+        new_ohlc: Vec<(NaiveDate, OHLC)>,
+        rule: ConflictRule,
+    ) -> Result<Vec<(NaiveDate, OHLC)>> {
+        let conflict = match rule {
+            ConflictRule::Replace => "REPLACE",
+            ConflictRule::Keep => "IGNORE",
+            ConflictRule::Fail => {
+                let known = self.load(isin)?.into_iter().collect::<HashMap<_, _>>();
+                check_conflicts(isin, &known, &new_ohlc, rule)?;
+                "REPLACE"
+            }
+        };
+        self.in_transaction(|| self.write_bars(isin, &new_ohlc, conflict))?;
+        self.load(isin)
+    }
+
+    fn load_meta(&self, isin: &str) -> Result<Option<InstrumentMeta>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, currency FROM meta WHERE isin = ?1")?;
+        let mut rows = stmt.query_map(params![isin], |row| {
+            Ok(InstrumentMeta {
+                isin: isin.to_string(),
+                name: row.get(0)?,
+                currency: row.get(1)?,
+            })
+        })?;
+        match rows.next() {
+            Some(meta) => Ok(Some(meta?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_meta(&self, meta: &InstrumentMeta) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO meta (isin, name, currency) VALUES (?1, ?2, ?3)",
+            params![meta.isin, meta.name, meta.currency],
+        )?;
+        Ok(())
+    }
+
+    fn log_fetch(&self, log: &FetchLog) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO fetch_log (isin, fetched, url, bars) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                log.isin,
+                log.fetched.format(FETCH_TIME_FORMAT).to_string(),
+                log.url,
+                log.bars as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn fetch_logs(&self, isin: &str) -> Result<Vec<FetchLog>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT fetched, url, bars FROM fetch_log WHERE isin = ?1 ORDER BY fetched")?;
+        let rows = stmt
+            .query_map(params![isin], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut logs = vec![];
+        for (fetched, url, bars) in rows.into_iter() {
+            logs.push(FetchLog {
+                isin: isin.to_string(),
+                fetched: NaiveDateTime::parse_from_str(&fetched, FETCH_TIME_FORMAT)?,
+                url,
+                bars: bars as usize,
+            });
+        }
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{copy_store, FileStore};
+
+    #[test]
+    fn save_load_and_merge() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let bar = |close| OHLC {
+            open: 100.0,
+            high: 102.5,
+            low: 99.25,
+            close,
+            volume: None,
+        };
+        let (d1, d2, d3) = (
+            NaiveDate::from_ymd(2019, 10, 29),
+            NaiveDate::from_ymd(2019, 10, 30),
+            NaiveDate::from_ymd(2019, 10, 31),
+        );
+        assert!(store.load("DE0008469008").unwrap().is_empty());
+
+        store
+            .save("DE0008469008", &[(d1, bar(101.0)), (d2, bar(101.5))])
+            .unwrap();
+        assert_eq!(
+            store.load("DE0008469008").unwrap(),
+            vec![(d1, bar(101.0)), (d2, bar(101.5))]
+        );
+        store.save("DE0008469008", &[(d2, bar(100.5))]).unwrap();
+        assert_eq!(store.load("DE0008469008").unwrap(), vec![(d2, bar(100.5))]);
+
+        let merged = store
+            .merge("DE0008469008", vec![(d3, bar(102.0)), (d2, bar(101.75))])
+            .unwrap();
+        assert_eq!(merged, vec![(d2, bar(101.75)), (d3, bar(102.0))]);
+        assert_eq!(store.load("DE0008469008").unwrap(), merged);
+        assert_eq!(store.isins().unwrap(), vec!["DE0008469008".to_string()]);
+    }
+
+    #[test]
+    fn merge_with_conflict_rules() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let bar = |close| OHLC {
+            open: 100.0,
+            high: 102.5,
+            low: 99.25,
+            close,
+            volume: None,
+        };
+        let (d1, d2) = (
+            NaiveDate::from_ymd(2019, 10, 30),
+            NaiveDate::from_ymd(2019, 10, 31),
+        );
+        store.save("DE0008469008", &[(d1, bar(101.0))]).unwrap();
+
+        let kept = store
+            .merge_with(
+                "DE0008469008",
+                vec![(d1, bar(100.5)), (d2, bar(101.5))],
+                ConflictRule::Keep,
+            )
+            .unwrap();
+        assert_eq!(kept, vec![(d1, bar(101.0)), (d2, bar(101.5))]);
+
+        // The same bar again is no conflict, a different one fails without writing.
+        let same = store
+            .merge_with("DE0008469008", vec![(d1, bar(101.0))], ConflictRule::Fail)
+            .unwrap();
+        assert_eq!(same, kept);
+        assert!(store
+            .merge_with(
+                "DE0008469008",
+                vec![(d2, bar(102.0)), (d1, bar(100.5))],
+                ConflictRule::Fail,
+            )
+            .is_err());
+        assert_eq!(store.load("DE0008469008").unwrap(), kept);
+
+        let replaced = store
+            .merge_with(
+                "DE0008469008",
+                vec![(d1, bar(100.5))],
+                ConflictRule::Replace,
+            )
+            .unwrap();
+        assert_eq!(replaced, vec![(d1, bar(100.5)), (d2, bar(101.5))]);
+
+        // A volume only conflicts with another known volume.
+        let traded = |volume| OHLC {
+            volume,
+            ..bar(100.5)
+        };
+        store
+            .merge_with(
+                "DE0008469008",
+                vec![(d1, traded(Some(1000.0)))],
+                ConflictRule::Replace,
+            )
+            .unwrap();
+        assert!(store
+            .merge_with(
+                "DE0008469008",
+                vec![(d1, traded(Some(2000.0)))],
+                ConflictRule::Fail,
+            )
+            .is_err());
+        assert!(store
+            .merge_with("DE0008469008", vec![(d1, traded(None))], ConflictRule::Fail)
+            .is_ok());
+    }
+
+    #[test]
+    fn meta_and_fetch_logs() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        assert!(store.load_meta("US2605661048").unwrap().is_none());
+        assert!(store.fetch_logs("US2605661048").unwrap().is_empty());
+
+        store
+            .save_meta(&InstrumentMeta {
+                isin: "US2605661048".to_string(),
+                name: Some("Dow Jones".to_string()),
+                currency: None,
+            })
+            .unwrap();
+        store
+            .save_meta(&InstrumentMeta {
+                isin: "US2605661048".to_string(),
+                name: Some("Dow Jones Industrial Average".to_string()),
+                currency: Some("USD".to_string()),
+            })
+            .unwrap();
+        let meta = store.load_meta("US2605661048").unwrap().unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Dow Jones Industrial Average"));
+        assert_eq!(meta.currency.as_deref(), Some("USD"));
+
+        for (day, bars) in [(31, 1), (30, 21)].iter() {
+            store
+                .log_fetch(&FetchLog {
+                    isin: "US2605661048".to_string(),
+                    fetched: NaiveDate::from_ymd(2019, 10, *day).and_hms(18, 5, 0),
+                    url: format!("https://example.com/{}", day),
+                    bars: *bars,
+                })
+                .unwrap();
+        }
+        let logs = store.fetch_logs("US2605661048").unwrap();
+        let logs = logs
+            .iter()
+            .map(|l| (l.fetched.to_string(), l.url.as_str(), l.bars))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logs,
+            vec![
+                (
+                    "2019-10-30 18:05:00".to_string(),
+                    "https://example.com/30",
+                    21
+                ),
+                (
+                    "2019-10-31 18:05:00".to_string(),
+                    "https://example.com/31",
+                    1
+                ),
+            ]
+        );
+        // Known from its metadata and fetch logs alone.
+        assert_eq!(store.isins().unwrap(), vec!["US2605661048".to_string()]);
+    }
+
+    #[test]
+    fn copy_to_file_store_and_back() {
+        let sqlite = SqliteStore::open(":memory:").unwrap();
+        let day = NaiveDate::from_ymd(2019, 10, 31);
+        let ohlc = vec![(
+            day,
+            OHLC {
+                open: 12910.25,
+                high: 12962.5,
+                low: 12851.75,
+                close: 12866.5,
+                volume: None,
+            },
+        )];
+        sqlite.save("DE0008469008", &ohlc).unwrap();
+        sqlite
+            .save_meta(&InstrumentMeta {
+                isin: "DE0008469008".to_string(),
+                name: Some("DAX".to_string()),
+                currency: Some("EUR".to_string()),
+            })
+            .unwrap();
+        sqlite
+            .log_fetch(&FetchLog {
+                isin: "DE0008469008".to_string(),
+                fetched: day.and_hms(18, 5, 0),
+                url: "https://example.com/dax".to_string(),
+                bars: 1,
+            })
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("sqlite-copy-{}", std::process::id()));
+        let files = FileStore::new(&dir);
+        let back = SqliteStore::open(":memory:").unwrap();
+        let copied = (
+            copy_store(&sqlite, &files),
+            copy_store(&files, &back),
+            // Copying again must not repeat the fetch logs.
+            copy_store(&files, &back),
+        );
+        let loaded = (
+            files.load("DE0008469008"),
+            files.load_meta("DE0008469008"),
+            files.fetch_logs("DE0008469008"),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            (copied.0.unwrap(), copied.1.unwrap(), copied.2.unwrap()),
+            (1, 1, 1)
+        );
+        assert_eq!(loaded.0.unwrap(), ohlc);
+        assert_eq!(loaded.1.unwrap().unwrap().name.as_deref(), Some("DAX"));
+        assert_eq!(loaded.2.unwrap().len(), 1);
+
+        assert_eq!(back.load("DE0008469008").unwrap(), ohlc);
+        let meta = back.load_meta("DE0008469008").unwrap().unwrap();
+        assert_eq!(
+            (meta.name.as_deref(), meta.currency.as_deref()),
+            (Some("DAX"), Some("EUR"))
+        );
+        let logs = back.fetch_logs("DE0008469008").unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].fetched, day.and_hms(18, 5, 0));
+        assert_eq!(logs[0].url, "https://example.com/dax");
+        assert_eq!(logs[0].bars, 1);
+    }
+}
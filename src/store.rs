@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use error_chain::bail;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+use crate::error_def::*;
+use crate::ohlc::OHLC;
+use crate::sqlite_store::SqliteStore;
+
+/// Descriptive data of an instrument beside its price history.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct InstrumentMeta {
+    pub isin: String,
+    pub name: Option<String>,
+    pub currency: Option<String>,
+}
+
+/// One download attempt of the updater.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct FetchLog {
+    pub isin: String,
+    pub fetched: NaiveDateTime,
+    pub url: String,
+    pub bars: usize,
+}
+
+/// What to do when merged data has a different bar for a day already stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictRule {
+    /// The new bar wins. This is what the updater does.
+    Replace,
+    /// The stored bar wins.
+    Keep,
+    /// Abort the merge without writing anything.
+    Fail,
+}
+
+impl FromStr for ConflictRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ConflictRule> {
+        match s {
+            "replace" => Ok(ConflictRule::Replace),
+            "keep" => Ok(ConflictRule::Keep),
+            "fail" => Ok(ConflictRule::Fail),
+            _ => bail!("Unknown conflict rule: {}", s),
+        }
+    }
+}
+
+/// Storage of the histories, instrument metadata and fetch logs of all instruments.
+pub trait Store {
+    /// All ISINs known to the store, sorted.
+    fn isins(&self) -> Result<Vec<String>>;
+
+    /// Load the history of one ISIN sorted by date. An unknown ISIN yields an empty history.
+    fn load(&self, isin: &str) -> Result<Vec<(NaiveDate, OHLC)>>;
+
+    /// Replace the history of one ISIN. The data is expected sorted by date.
+    fn save(&self, isin: &str, ohlc_data: &[(NaiveDate, OHLC)]) -> Result<()>;
+
+    fn load_meta(&self, isin: &str) -> Result<Option<InstrumentMeta>>;
+
+    fn save_meta(&self, meta: &InstrumentMeta) -> Result<()>;
+
+    fn log_fetch(&self, log: &FetchLog) -> Result<()>;
+
+    fn fetch_logs(&self, isin: &str) -> Result<Vec<FetchLog>>;
+
+    /// Merge new bars into the history of one ISIN and save the result.
+    /// New bars replace stored bars of the same day.
+    fn merge(
+        &self,
+        isin: &str,
+        new_ohlc: Vec<(NaiveDate, OHLC)>,
+    ) -> Result<Vec<(NaiveDate, OHLC)>> {
+        self.merge_with(isin, new_ohlc, ConflictRule::Replace)
+    }
+
+    /// Merge new bars into the history of one ISIN and save the result,
+    /// resolving days present in both according to `rule`.
+    fn merge_with(
+        &self,
+        isin: &str,
+        new_ohlc: Vec<(NaiveDate, OHLC)>,
+        rule: ConflictRule,
+    ) -> Result<Vec<(NaiveDate, OHLC)>> {
+        let mut all_ohlc = self.load(isin)?.into_iter().collect::<HashMap<_, _>>();
+        check_conflicts(isin, &all_ohlc, &new_ohlc, rule)?;
+        for (day, ohlc) in new_ohlc.into_iter() {
+            if rule == ConflictRule::Keep && all_ohlc.contains_key(&day) {
+                continue;
+            }
+            all_ohlc.insert(day, ohlc);
+        }
+        let mut all_ohlc = all_ohlc.into_iter().collect::<Vec<_>>();
+        all_ohlc.sort_by_key(|e| e.0);
+        self.save(isin, &all_ohlc)?;
+        Ok(all_ohlc)
+    }
+}
+
+/// Fail with the first day of `new_ohlc` that differs from `known`, if `rule` asks for it.
+/// Volumes only differ if both are known, since many sources have none.
+pub fn check_conflicts(
+    isin: &str,
+    known: &HashMap<NaiveDate, OHLC>,
+    new_ohlc: &[(NaiveDate, OHLC)],
+    rule: ConflictRule,
+) -> Result<()> {
+    if rule == ConflictRule::Fail {
+        for (day, ohlc) in new_ohlc.iter() {
+            if let Some(k) = known.get(day) {
+                let prices_differ = (k.open, k.high, k.low, k.close)
+                    != (ohlc.open, ohlc.high, ohlc.low, ohlc.close);
+                let volumes_differ = match (k.volume, ohlc.volume) {
+                    (Some(a), Some(b)) => a != b,
+                    _ => false,
+                };
+                if prices_differ || volumes_differ {
+                    bail!(
+                        "Conflict for {} on {}: stored {} volume {:?} new {} volume {:?}",
+                        isin,
+                        day,
+                        k,
+                        k.volume,
+                        ohlc,
+                        ohlc.volume
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One bar of one instrument, the unit of the exports.
+pub type Row = (String, NaiveDate, OHLC);
+
+/// The bars of the given ISINs (all of the store if empty), ordered by ISIN and date.
+pub fn load_rows(store: &dyn Store, isins: &[String]) -> Result<Vec<Row>> {
+    let isins = if isins.is_empty() {
+        store.isins()?
+    } else {
+        isins.to_vec()
+    };
+    let mut rows = vec![];
+    for isin in isins.into_iter() {
+        for (day, ohlc) in store.load(&isin)?.into_iter() {
+            rows.push((isin.clone(), day, ohlc));
+        }
+    }
+    Ok(rows)
+}
+
+/// Write bars back into the store, replacing the histories of the ISINs they contain.
+/// Returns the ISINs written.
+pub fn save_rows(store: &dyn Store, rows: Vec<Row>) -> Result<Vec<String>> {
+    let mut per_isin = BTreeMap::new();
+    for (isin, day, ohlc) in rows.into_iter() {
+        per_isin
+            .entry(isin)
+            .or_insert_with(Vec::new)
+            .push((day, ohlc));
+    }
+    let mut isins = vec![];
+    for (isin, mut ohlc_data) in per_isin.into_iter() {
+        ohlc_data.sort_by_key(|e| e.0);
+        store.save(&isin, &ohlc_data)?;
+        isins.push(isin);
+    }
+    Ok(isins)
+}
+
+/// Copy everything of one store into another.
+/// Returns the number of instruments copied.
+pub fn copy_store(from: &dyn Store, to: &dyn Store) -> Result<usize> {
+    let isins = from.isins()?;
+    for isin in isins.iter() {
+        to.save(isin, &from.load(isin)?)?;
+        if let Some(meta) = from.load_meta(isin)? {
+            to.save_meta(&meta)?;
+        }
+        let known = to.fetch_logs(isin)?;
+        for log in from.fetch_logs(isin)?.iter() {
+            if !known
+                .iter()
+                .any(|k| k.fetched == log.fetched && k.url == log.url)
+            {
+                to.log_fetch(log)?;
+            }
+        }
+    }
+    Ok(isins.len())
+}
+
+/// Open a store: a path ending in `.db` or `.sqlite` is an SQLite database,
+/// anything else the directory of a file store.
+pub fn open_store(path: &str) -> Result<Box<dyn Store>> {
+    if path.ends_with(".db") || path.ends_with(".sqlite") {
+        Ok(Box::new(SqliteStore::open(path)?))
+    } else {
+        Ok(Box::new(FileStore::new(path)))
+    }
+}
+
+/// The store at `path`, or the file store in the home directory without one.
+pub fn open_store_or_home(path: Option<&str>) -> Result<Box<dyn Store>> {
+    match path {
+        Some(path) => open_store(path),
+        None => Ok(Box::new(FileStore::in_home())),
+    }
+}
+
+const FETCH_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// The per-ISIN directory layout used by the updater:
+/// `<root>/<ISIN>/ohlc.csv`, `<root>/<ISIN>/meta.txt` and `<root>/<ISIN>/fetch.log`.
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> FileStore {
+        FileStore { root: root.into() }
+    }
+
+    /// The store below `~/data/stock`, as used by the analysis tools.
+    pub fn in_home() -> FileStore {
+        let mut root = dirs::home_dir().unwrap();
+        root.push("data");
+        root.push("stock");
+        FileStore::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn isin_dir(&self, isin: &str) -> PathBuf {
+        self.root.join(isin)
+    }
+}
+
+impl Store for FileStore {
+    fn isins(&self) -> Result<Vec<String>> {
+        let mut isins = vec![];
+        for entry in self.root.read_dir()? {
+            let entry = entry?;
+            if let Ok(isin) = entry.file_name().into_string() {
+                if entry.metadata()?.is_dir() && isin.len() == 12 {
+                    isins.push(isin);
+                }
+            }
+        }
+        isins.sort();
+        Ok(isins)
+    }
+
+    fn load(&self, isin: &str) -> Result<Vec<(NaiveDate, OHLC)>> {
+        match File::open(self.isin_dir(isin).join("ohlc.csv")) {
+            Ok(f) => OHLC::load_file(f),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn save(&self, isin: &str, ohlc_data: &[(NaiveDate, OHLC)]) -> Result<()> {
+        let dir = self.isin_dir(isin);
+        std::fs::create_dir_all(&dir)?;
+        let f = File::create(dir.join("ohlc.csv"))?;
+        OHLC::save_file(f, ohlc_data)
+    }
+
+    fn load_meta(&self, isin: &str) -> Result<Option<InstrumentMeta>> {
+        let f = match File::open(self.isin_dir(isin).join("meta.txt")) {
+            Ok(f) => f,
+            _ => return Ok(None),
+        };
+        let mut meta = InstrumentMeta {
+            isin: isin.to_string(),
+            ..Default::default()
+        };
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            let mut kv = line.splitn(2, '=');
+            match (kv.next().map(str::trim), kv.next().map(str::trim)) {
+                (Some("name"), Some(v)) => meta.name = Some(v.to_string()),
+                (Some("currency"), Some(v)) => meta.currency = Some(v.to_string()),
+                _ => (),
+            }
+        }
+        Ok(Some(meta))
+    }
+
+    fn save_meta(&self, meta: &InstrumentMeta) -> Result<()> {
+        let dir = self.isin_dir(&meta.isin);
+        std::fs::create_dir_all(&dir)?;
+        let mut f = File::create(dir.join("meta.txt"))?;
+        if let Some(ref name) = meta.name {
+            writeln!(f, "name={}", name)?;
+        }
+        if let Some(ref currency) = meta.currency {
+            writeln!(f, "currency={}", currency)?;
+        }
+        Ok(())
+    }
+
+    fn log_fetch(&self, log: &FetchLog) -> Result<()> {
+        let dir = self.isin_dir(&log.isin);
+        std::fs::create_dir_all(&dir)?;
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("fetch.log"))?;
+        writeln!(
+            f,
+            "{} {} {}",
+            log.fetched.format(FETCH_TIME_FORMAT),
+            log.bars,
+            log.url
+        )?;
+        Ok(())
+    }
+
+    fn fetch_logs(&self, isin: &str) -> Result<Vec<FetchLog>> {
+        let f = match File::open(self.isin_dir(isin).join("fetch.log")) {
+            Ok(f) => f,
+            _ => return Ok(vec![]),
+        };
+        let mut logs = vec![];
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            let fields = line.splitn(3, ' ').collect::<Vec<_>>();
+            if fields.len() == 3 {
+                logs.push(FetchLog {
+                    isin: isin.to_string(),
+                    fetched: NaiveDateTime::parse_from_str(fields[0], FETCH_TIME_FORMAT)?,
+                    bars: fields[1].parse()?,
+                    url: fields[2].to_string(),
+                });
+            }
+        }
+        Ok(logs)
+    }
+}
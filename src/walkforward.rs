@@ -0,0 +1,136 @@
+use chrono::NaiveDate;
+use error_chain::bail;
+use ndarray::Array1;
+
+use crate::cluster::Method;
+use crate::error_def::*;
+use crate::features::Pipeline;
+use crate::market::Market;
+use crate::markov::{state_means, MarkovSettings, TransitionModel};
+use crate::som::seeded_rng;
+
+/// Settings of a walk-forward run.
+#[derive(Clone, Debug)]
+pub struct WalkForward {
+    /// Number of days the clusterer is trained on.
+    pub window: usize,
+    /// Retrain every `retrain` days. In between, the last clusterer is used.
+    pub retrain: usize,
+    pub method: Method,
+    pub iterations: u32,
+    pub seed: u64,
+    pub markov: MarkovSettings,
+}
+
+/// The estimate for one day made from the days before, and what happened.
+/// Both in raw feature values.
+#[derive(Clone, Debug)]
+pub struct Prediction {
+    pub day: NaiveDate,
+    pub predicted: Vec<f64>,
+    pub actual: Vec<f64>,
+    /// The raw row of the day before.
+    pub previous: Vec<f64>,
+    /// Mean raw row of the training window.
+    pub window_mean: Vec<f64>,
+}
+
+impl WalkForward {
+    /// Predict every day after the first window out of sample.
+    /// Days without an estimate, because the transition model has no distribution, are skipped.
+    pub fn run(&self, market: &Market, pipeline: &mut Pipeline) -> Result<Vec<Prediction>> {
+        let (ts, rows): (Vec<_>, Vec<_>) = pipeline.raw_rows(market).into_iter().unzip();
+        let days = ts.into_iter().map(|t| market.day(t)).collect::<Vec<_>>();
+        if self.window < 2 || rows.len() <= self.window {
+            bail!(
+                "Walk-forward needs more than {} days, got {}",
+                self.window,
+                rows.len()
+            );
+        }
+
+        let mut rng = seeded_rng(self.seed);
+        let mut clusterer = None;
+        let mut days_to_retrain = 0;
+        let mut predictions = vec![];
+        for i in self.window - 1..rows.len() - 1 {
+            let train = &rows[i + 1 - self.window..=i];
+            if days_to_retrain == 0 {
+                days_to_retrain = self.retrain.max(1);
+                pipeline.fit(train);
+                let data = pipeline.transform(train);
+                let mut c = self.method.build(pipeline.width(), &mut rng);
+                c.fit(&data, self.iterations, &mut rng);
+                clusterer = Some(c);
+            }
+            days_to_retrain -= 1;
+            let clusterer = clusterer.as_ref().unwrap();
+
+            let data = pipeline.transform(train);
+            let winners = clusterer.assign_all(&data);
+            let transitions = TransitionModel::fit(&winners, &self.markov);
+            let means = state_means(&winners, &data);
+            if let Some(est) = transitions.estimate(&winners, &means) {
+                let mut window_mean = Array1::<f64>::zeros(pipeline.width());
+                for row in train.iter() {
+                    window_mean = window_mean + Array1::from(row.clone());
+                }
+                window_mean /= train.len() as f64;
+                predictions.push(Prediction {
+                    day: days[i + 1],
+                    predicted: pipeline.denormalise(&est.to_vec()),
+                    actual: rows[i + 1].clone(),
+                    previous: rows[i].clone(),
+                    window_mean: window_mean.to_vec(),
+                });
+            }
+        }
+        Ok(predictions)
+    }
+}
+
+/// Errors of one way of predicting a column.
+#[derive(Clone, Debug)]
+pub struct Score {
+    pub name: String,
+    /// Share of days with the sign of the prediction equal to the sign of the actual value.
+    /// Predictions of exactly zero count as misses.
+    pub hit_rate: f64,
+    /// `None` for baselines which only predict a direction.
+    pub mean_absolute_error: Option<f64>,
+}
+
+fn score(name: &str, pairs: &[(f64, f64)]) -> Score {
+    let n = pairs.len().max(1) as f64;
+    let hits = pairs
+        .iter()
+        .filter(|(p, a)| (*p > 0.0 && *a > 0.0) || (*p < 0.0 && *a < 0.0))
+        .count();
+    let mae = pairs.iter().map(|(p, a)| (p - a).abs()).sum::<f64>() / n;
+    Score {
+        name: name.to_string(),
+        hit_rate: hits as f64 / n,
+        mean_absolute_error: Some(mae),
+    }
+}
+
+/// Score the estimator, called `name`, on column `col` against naive baselines:
+/// no change (zero), same as the day before, the training window mean, and always up.
+pub fn evaluate(name: &str, predictions: &[Prediction], col: usize) -> Vec<Score> {
+    let pairs = |f: &dyn Fn(&Prediction) -> f64| {
+        predictions
+            .iter()
+            .map(|p| (f(p), p.actual[col]))
+            .collect::<Vec<_>>()
+    };
+    vec![
+        score(name, &pairs(&|p| p.predicted[col])),
+        score("zero", &pairs(&|_| 0.0)),
+        score("previous", &pairs(&|p| p.previous[col])),
+        score("window mean", &pairs(&|p| p.window_mean[col])),
+        Score {
+            mean_absolute_error: None,
+            ..score("always up", &pairs(&|_| 1.0))
+        },
+    ]
+}